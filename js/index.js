@@ -1,9 +1,14 @@
 import '../styles/style.scss'
 import * as bootstrap from 'bootstrap';
-import { TileCorruptorAppInst, wasm_get_builtin_graphics_codecs } from '../pkg/index.js';
+import { TileCorruptorAppInst, CodecDescriptor, BitOrderKind, wasm_get_graphics_codecs, wasm_format_codec, wasm_load_codecs } from '../pkg/index.js';
 
 let rust_app_inst = undefined;
+let open_file_name = undefined;
+let open_file_names = [];
 let global_is_tiled = true;
+// the pasted MAME layout while it is the current codec, so that RGN_FRAC can be
+// resolved again against newly opened files
+let mame_layout_text = undefined;
 
 const CODEC_HUMAN_NAMES = new Map([
     ["nes", "Tiled, 2bpp planar, non-interleaved (NES)"],
@@ -11,83 +16,371 @@ const CODEC_HUMAN_NAMES = new Map([
     ["gba-4bpp", "Tiled, 4bpp non-planar, LSB->MSB (GBA)"],
     ["genesis-4bpp", "Tiled, 4bpp non-planar, MSB->LSB (Genesis)"],
     ["tiled-8bpp", "Tiled, 8bpp non-planar (GBA, NDS)"],
+    ["sms-4bpp", "Tiled, 4bpp planar, row-interleaved (SMS, GG)"],
+    ["snes-2bpp", "Tiled, 2bpp planar, row-interleaved (SNES)"],
+    ["snes-3bpp", "Tiled, 3bpp planar, row-interleaved pair + 1 plane (SNES)"],
+    ["snes-4bpp", "Tiled, 4bpp planar, row-interleaved pairs (SNES)"],
+    ["snes-8bpp", "Tiled, 8bpp planar, row-interleaved pairs (SNES)"],
+    ["snes-mode7-tiles", "Tiled, 8bpp in odd bytes (SNES Mode 7 tiles)"],
+    ["snes-mode7-map", "128x128 tilemap + tiles (SNES Mode 7 map)"],
+    ["pce-4bpp", "Tiled, 4bpp planar, row-interleaved pairs (PCE)"],
+    ["pce-sprite", "Tiled 16x16, 4bpp planar, non-interleaved, 16-bit LE (PCE sprites)"],
+    ["neogeo-sprite", "Tiled 16x16, 4bpp planar, interleaved C ROM pair (Neo Geo)"],
+    ["vb-2bpp", "Tiled, 2bpp packed, LSB first (Virtual Boy)"],
+    ["ngp-2bpp", "Tiled, 2bpp packed, 16-bit LE, MSB first (NGP)"],
+    ["ws-2bpp", "Tiled, 2bpp planar, row-interleaved (WonderSwan)"],
+    ["ws-4bpp", "Tiled, 4bpp planar, row-interleaved (WonderSwan Color)"],
+    ["ws-4bpp-packed", "Tiled, 4bpp packed, high nibble first (WonderSwan Color)"],
     ["lin-1bpp-msbfirst", "Non-tiled, 1bpp, MSB->LSB"],
     ["lin-1bpp-lsbfirst", "Non-tiled, 1bpp, LSB->MSB"],
 ]);
 
-let builtin_codecs = wasm_get_builtin_graphics_codecs();
-let codecs_menu = document.getElementById("codecs_menu");
-for (const [codec_i, codec] of builtin_codecs.entries()) {
+function codec_human_name(codec) {
     let name = codec.i18n_name;
-    let is_tiled = codec.is_tiled;
     if (CODEC_HUMAN_NAMES.has(name))
         name = CODEC_HUMAN_NAMES.get(name);
+    return name;
+}
 
-    let a_elem = document.createElement("a");
-    a_elem.classList = "dropdown-item";
-    a_elem.href = "#";
-    a_elem.innerText = name;
-
-    a_elem.addEventListener("click", (e) => {
-        if (rust_app_inst !== undefined) {
-            rust_app_inst.change_codec(codec_i);
-            document.getElementById("cur_codec").innerText = name;
-            global_is_tiled = is_tiled;
-
-            if (global_is_tiled) {
-                document.getElementById("tile_pm_group").style.removeProperty("display");
-                document.getElementById("px_pm_group").style.setProperty("display", "none");
-            } else {
-                document.getElementById("tile_pm_group").style.setProperty("display", "none");
-                document.getElementById("px_pm_group").style.removeProperty("display");
-            }
-        }
-    });
+function codec_changed(name, is_tiled) {
+    document.getElementById("cur_codec").innerText = name;
+    global_is_tiled = is_tiled;
+    mame_layout_text = undefined;
 
-    let li_elem = document.createElement("li");
-    li_elem.appendChild(a_elem);
+    if (global_is_tiled) {
+        document.getElementById("tile_pm_group").style.removeProperty("display");
+        document.getElementById("px_pm_group").style.setProperty("display", "none");
+    } else {
+        document.getElementById("tile_pm_group").style.setProperty("display", "none");
+        document.getElementById("px_pm_group").style.removeProperty("display");
+    }
+}
+
+function select_codec(codec_i) {
+    let codec = graphics_codecs[codec_i];
+    rust_app_inst.change_codec(codec_i);
+    codec_changed(codec_human_name(codec), codec.is_tiled);
+}
 
-    codecs_menu.appendChild(li_elem);
+let custom_codec_modal = new bootstrap.Modal(document.getElementById("custom_codec_dialog"));
 
-    if (codec_i == 0)
-        document.getElementById("cur_codec").innerText = name;
+document.getElementById("codec_custom").addEventListener("click", (e) => {
+    if (rust_app_inst !== undefined)
+        custom_codec_modal.show();
+});
+
+function read_custom_codec_form() {
+    let num = (id) => {
+        let val = parseInt(document.getElementById(id).value) || 0;
+        if (val < 0)
+            throw new Error("sizes and paddings can't be negative");
+        return val;
+    };
+    let bit_order = (id) =>
+        document.getElementById(id).value == "lsb" ? BitOrderKind.LsbFirst : BitOrderKind.MsbFirst;
+
+    let desc = new CodecDescriptor();
+    desc.tiled = document.getElementById("cc_tiled").checked;
+    desc.bpp = num("cc_bpp");
+    desc.planar = document.getElementById("cc_planar").checked;
+    desc.planes_per_group = num("cc_planes_per_group");
+    desc.data_bit_order = bit_order("cc_data_bit_order");
+    desc.pixel_bit_order = bit_order("cc_pixel_bit_order");
+    desc.tile_w = num("cc_tile_w");
+    desc.tile_h = num("cc_tile_h");
+    desc.px_pad = num("cc_px_pad");
+    desc.row_pad = num("cc_row_pad");
+    desc.group_pad = num("cc_group_pad");
+    desc.final_pad = num("cc_final_pad");
+    return desc;
 }
 
-async function choose_new_file(e) {
+document.getElementById("cc_apply").addEventListener("click", (e) => {
+    let desc;
+    try {
+        desc = read_custom_codec_form();
+    } catch (err) {
+        alert("Invalid codec: " + err.message);
+        return;
+    }
+    let is_tiled = desc.tiled;
+    try {
+        rust_app_inst.set_custom_codec(desc);
+    } catch (err) {
+        alert("Invalid codec: " + err.message);
+        return;
+    } finally {
+        desc.free();
+    }
+    codec_changed("Custom", is_tiled);
+    custom_codec_modal.hide();
+});
+
+document.getElementById("cc_export").addEventListener("click", (e) => {
+    let name = document.getElementById("cc_name").value || "Custom";
+    let desc;
+    try {
+        desc = read_custom_codec_form();
+    } catch (err) {
+        alert("Invalid codec: " + err.message);
+        return;
+    }
+    let text = wasm_format_codec(name, desc);
+    desc.free();
+    download_bytes(new TextEncoder().encode(text), "text/plain", name + ".codec");
+});
+
+let mame_layout_modal = new bootstrap.Modal(document.getElementById("mame_layout_dialog"));
+
+document.getElementById("codec_mame_layout").addEventListener("click", (e) => {
+    if (rust_app_inst !== undefined)
+        mame_layout_modal.show();
+});
+
+document.getElementById("mame_layout_apply").addEventListener("click", (e) => {
+    let text = document.getElementById("mame_layout_text").value;
+    try {
+        rust_app_inst.set_mame_layout(text);
+    } catch (err) {
+        alert("Invalid layout: " + err.message);
+        return;
+    }
+    codec_changed("MAME layout", true);
+    mame_layout_text = text;
+    mame_layout_modal.hide();
+});
+
+let graphics_codecs = undefined;
+let codecs_menu = document.getElementById("codecs_menu");
+function rebuild_codecs_menu() {
+    for (const elem of codecs_menu.querySelectorAll(".codec-entry"))
+        elem.remove();
+
+    graphics_codecs = wasm_get_graphics_codecs();
+    for (const [codec_i, codec] of graphics_codecs.entries()) {
+        let name = codec_human_name(codec);
+
+        let a_elem = document.createElement("a");
+        a_elem.classList = "dropdown-item";
+        a_elem.href = "#";
+        a_elem.innerText = name;
+
+        a_elem.addEventListener("click", (e) => {
+            if (rust_app_inst !== undefined)
+                select_codec(codec_i);
+        });
+
+        let li_elem = document.createElement("li");
+        li_elem.classList = "codec-entry";
+        li_elem.appendChild(a_elem);
+
+        codecs_menu.appendChild(li_elem);
+    }
+}
+rebuild_codecs_menu();
+document.getElementById("cur_codec").innerText = codec_human_name(graphics_codecs[0]);
+
+document.getElementById("codec_load").addEventListener("click", (e) => {
+    if (rust_app_inst !== undefined)
+        document.getElementById("codecchooser").click();
+});
+
+async function choose_codec_file(e) {
     let file = e.target.files[0];
-    let file_data = await file.arrayBuffer();
-    let file_data_u8 = new Uint8Array(file_data);
+    let text = await file.text();
+    e.target.value = "";
+
+    try {
+        wasm_load_codecs(text);
+    } catch (err) {
+        alert("Could not load " + file.name + ": " + err.message);
+        return;
+    }
+    rebuild_codecs_menu();
+}
+
+async function choose_new_file(e) {
+    if (rust_app_inst !== undefined && rust_app_inst.is_dirty()) {
+        if (!confirm("Discard unsaved changes to " + open_file_name + "?"))
+            return;
+    }
+
+    // sorted so that e.g. "foo-c2.c2" comes before "foo-c10.c10"
+    let files = Array.from(e.target.files).sort((a, b) =>
+        a.name.localeCompare(b.name, undefined, { numeric: true }));
+    let interleaved = e.target.multiple;
+    e.target.value = "";
+    if (files.length == 0)
+        return;
+    if (interleaved && files.length != 2) {
+        alert("Select exactly two files to interleave");
+        return;
+    }
+    let file_datas = [];
+    for (const file of files)
+        file_datas.push(new Uint8Array(await file.arrayBuffer()));
 
     if (rust_app_inst !== undefined) {
         rust_app_inst.free();
     }
-    rust_app_inst = new TileCorruptorAppInst(file_data_u8);
+    if (file_datas.length == 2)
+        rust_app_inst = TileCorruptorAppInst.new_interleaved(file_datas[0], file_datas[1]);
+    else
+        rust_app_inst = new TileCorruptorAppInst(file_datas[0]);
+    if (mame_layout_text !== undefined) {
+        try {
+            rust_app_inst.set_mame_layout(mame_layout_text);
+        } catch (err) {
+            alert("The MAME layout does not fit the new file: " + err.message);
+            select_codec(0);
+        }
+    }
+    apply_sprite_size();
     rust_app_inst.resize();
     rust_app_inst.render();
     rust_app_inst.update_status_bar();
 
-    document.getElementById("open_fn").innerText = file.name;
+    open_file_names = files.map((f) => f.name);
+    open_file_name = open_file_names[0];
+    document.getElementById("open_fn").innerText = open_file_names.join(" + ");
 }
 
 document.getElementById("file_open").addEventListener("click", (e) => {
+    document.getElementById("filechooser").multiple = false;
     document.getElementById("filechooser").click();
 });
 
+document.getElementById("file_open_interleaved").addEventListener("click", (e) => {
+    document.getElementById("filechooser").multiple = true;
+    document.getElementById("filechooser").click();
+});
+
+function download_bytes(bytes, mime_type, file_name) {
+    let blob = new Blob([bytes], { type: mime_type });
+    let url = URL.createObjectURL(blob);
+
+    let download_elem = document.createElement("a");
+    download_elem.style = "display: none;";
+    download_elem.href = url;
+    download_elem.download = file_name;
+    document.body.appendChild(download_elem);
+    download_elem.click();
+    URL.revokeObjectURL(url);
+    document.body.removeChild(download_elem);
+}
+
+document.getElementById("file_save").addEventListener("click", (e) => {
+    if (rust_app_inst !== undefined) {
+        if (rust_app_inst.num_interleaved_files() > 0) {
+            for (const [i, name] of open_file_names.entries()) {
+                let bytes = rust_app_inst.export_deinterleaved(i);
+                if (bytes !== undefined)
+                    download_bytes(bytes, "application/octet-stream", name);
+            }
+        } else {
+            let bytes = rust_app_inst.export_data();
+            download_bytes(bytes, "application/octet-stream", open_file_name);
+        }
+        rust_app_inst.mark_saved();
+    }
+});
+
+function patch_file_name(file_name, ext) {
+    let dot = file_name.lastIndexOf(".");
+    let base = dot > 0 ? file_name.substring(0, dot) : file_name;
+    return base + ext;
+}
+
+// interleaved files each get their own patch
+function export_patches(ext, create_patch) {
+    if (rust_app_inst === undefined)
+        return;
+    for (const [i, name] of open_file_names.entries()) {
+        try {
+            let bytes = create_patch(i);
+            download_bytes(bytes, "application/octet-stream", patch_file_name(name, ext));
+        } catch (err) {
+            alert("Could not create patch for " + name + ": " + err.message);
+        }
+    }
+}
+
+document.getElementById("file_export_ips").addEventListener("click", (e) => {
+    export_patches(".ips", (i) => rust_app_inst.export_ips(i));
+});
+
+document.getElementById("file_export_bps").addEventListener("click", (e) => {
+    export_patches(".bps", (i) => rust_app_inst.export_bps(i));
+});
+
+document.getElementById("file_export_ups").addEventListener("click", (e) => {
+    export_patches(".ups", (i) => rust_app_inst.export_ups(i));
+});
+
+document.getElementById("file_apply_patch").addEventListener("click", (e) => {
+    if (rust_app_inst !== undefined)
+        document.getElementById("patchchooser").click();
+});
+
+async function choose_patch(e) {
+    let file = e.target.files[0];
+    let file_data = new Uint8Array(await file.arrayBuffer());
+    e.target.value = "";
+
+    // interleaved files each have their own patches, picked by name if possible
+    let idx = 0;
+    if (open_file_names.length > 1) {
+        idx = open_file_names.findIndex((name) =>
+            patch_file_name(name, "") == patch_file_name(file.name, ""));
+        if (idx < 0) {
+            let choices = open_file_names.map((name, i) => (i + 1) + ": " + name).join("\n");
+            idx = parseInt(prompt("Which file should " + file.name + " be applied to?\n" + choices)) - 1;
+            if (!(idx >= 0 && idx < open_file_names.length))
+                return;
+        }
+    }
+
+    try {
+        if (!rust_app_inst.apply_patch(idx, file_data))
+            alert(file.name + " changed the file size, so earlier edits can no longer be undone");
+    } catch (err) {
+        alert("Could not apply " + file.name + ": " + err.message);
+    }
+}
+
 document.getElementById("file_export").addEventListener("click", (e) => {
     if (rust_app_inst !== undefined) {
         let bytes = rust_app_inst.export_png();
-        let blob = new Blob([bytes], { type: "image/png" });
-        let url = URL.createObjectURL(blob);
+        download_bytes(bytes, "image/png", "export.png");
+    }
+});
+
+document.getElementById("file_import").addEventListener("click", (e) => {
+    if (rust_app_inst !== undefined)
+        document.getElementById("pngchooser").click();
+});
 
-        let download_elem = document.createElement("a");
-        download_elem.style = "display: none;";
-        download_elem.href = url;
-        download_elem.download = "export.png";
-        document.body.appendChild(download_elem);
-        download_elem.click();
-        URL.revokeObjectURL(url);
-        document.body.removeChild(download_elem);
+async function choose_import_png(e) {
+    let file = e.target.files[0];
+    let file_data = new Uint8Array(await file.arrayBuffer());
+    e.target.value = "";
+
+    try {
+        let lossy_px = rust_app_inst.import_png(file_data);
+        if (lossy_px > 0)
+            alert(lossy_px + " pixels did not exactly match the palette and were mapped to the nearest color");
+    } catch (err) {
+        alert("Could not import " + file.name + ": " + err.message);
     }
+}
+
+document.getElementById("edit_undo").addEventListener("click", (e) => {
+    if (rust_app_inst !== undefined)
+        rust_app_inst.undo();
+});
+document.getElementById("edit_redo").addEventListener("click", (e) => {
+    if (rust_app_inst !== undefined)
+        rust_app_inst.redo();
 });
 
 document.getElementById("gfx_w_m").addEventListener("click", (e) => {
@@ -155,6 +448,19 @@ document.addEventListener("keydown", (e) => {
     if (rust_app_inst === undefined)
         return;
 
+    if ((e.ctrlKey || e.metaKey) && !e.altKey) {
+        if (e.key == "z" && !e.shiftKey) {
+            rust_app_inst.undo();
+            e.preventDefault();
+            return;
+        }
+        if (e.key == "y" || (e.key == "Z" && e.shiftKey)) {
+            rust_app_inst.redo();
+            e.preventDefault();
+            return;
+        }
+    }
+
     if (!e.altKey) {
         if (global_is_tiled) {
             if (e.key == "ArrowLeft" && !e.shiftKey) {
@@ -220,6 +526,108 @@ document.getElementById("goto_offs_btn").addEventListener("click", (e) => {
     rust_app_inst.go_to_offset(goto_addr_elem.value);
 });
 
+function draw_tool() {
+    return document.querySelector("input[name=draw_tool]:checked").value;
+}
+function draw_color() {
+    return parseInt(document.getElementById("draw_color").value) || 0;
+}
+
+function apply_sprite_size() {
+    let w = parseInt(document.getElementById("sprite_w").value) || 1;
+    let h = parseInt(document.getElementById("sprite_h").value) || 1;
+    let tiles_cm = document.getElementById("sprite_tiles_cm").checked;
+    let blocks_cm = document.getElementById("sprite_blocks_cm").checked;
+    rust_app_inst.set_arrangement(w, h, tiles_cm, blocks_cm);
+}
+
+for (const id of ["sprite_w", "sprite_h", "sprite_tiles_cm", "sprite_blocks_cm"]) {
+    document.getElementById(id).addEventListener("change", (e) => {
+        if (rust_app_inst !== undefined)
+            apply_sprite_size();
+    });
+}
+
+// switches to `codec_name` and groups tiles the way that system's sprites are stored
+function set_view_preset(codec_name, w, h, tiles_cm) {
+    if (rust_app_inst === undefined)
+        return;
+    select_codec(graphics_codecs.findIndex((c) => c.i18n_name == codec_name));
+    document.getElementById("sprite_w").value = w;
+    document.getElementById("sprite_h").value = h;
+    document.getElementById("sprite_tiles_cm").checked = tiles_cm;
+    document.getElementById("sprite_blocks_cm").checked = false;
+    apply_sprite_size();
+}
+
+document.getElementById("view_tiles").addEventListener("click", (e) => {
+    document.getElementById("sprite_w").value = 1;
+    document.getElementById("sprite_h").value = 1;
+    document.getElementById("sprite_tiles_cm").checked = false;
+    document.getElementById("sprite_blocks_cm").checked = false;
+    if (rust_app_inst !== undefined)
+        apply_sprite_size();
+});
+document.getElementById("view_nes_8x16").addEventListener("click", (e) => {
+    // the PPU takes the top half from an even tile and the bottom half from the next one
+    set_view_preset("nes", 1, 2, false);
+});
+document.getElementById("view_genesis").addEventListener("click", (e) => {
+    // VDP sprites are stored column by column, 1-4 tiles per side; their size is up to the user
+    let clamp = (id) => {
+        let val = parseInt(document.getElementById(id).value) || 1;
+        return Math.min(Math.max(val, 1), 4);
+    };
+    set_view_preset("genesis-4bpp", clamp("sprite_w"), clamp("sprite_h"), true);
+});
+
+let draw_last_pos = undefined;
+let canvas_elem = document.getElementById("canvas");
+canvas_elem.addEventListener("mousedown", (e) => {
+    if (rust_app_inst === undefined || e.button != 0)
+        return;
+
+    let tool = draw_tool();
+    if (tool != "none")
+        rust_app_inst.begin_edit();
+    if (tool == "pencil") {
+        rust_app_inst.paint_at(e.offsetX, e.offsetY, draw_color());
+        draw_last_pos = [e.offsetX, e.offsetY];
+    } else if (tool == "line") {
+        draw_last_pos = [e.offsetX, e.offsetY];
+    } else if (tool == "fill") {
+        rust_app_inst.flood_fill(e.offsetX, e.offsetY, draw_color());
+        rust_app_inst.end_edit();
+    }
+});
+canvas_elem.addEventListener("mousemove", (e) => {
+    if (rust_app_inst === undefined || draw_last_pos === undefined)
+        return;
+
+    if (draw_tool() == "pencil") {
+        rust_app_inst.draw_line(draw_last_pos[0], draw_last_pos[1], e.offsetX, e.offsetY, draw_color());
+        draw_last_pos = [e.offsetX, e.offsetY];
+    }
+});
+document.addEventListener("mouseup", (e) => {
+    if (rust_app_inst === undefined || draw_last_pos === undefined)
+        return;
+
+    if (draw_tool() == "line" && e.target === canvas_elem)
+        rust_app_inst.draw_line(draw_last_pos[0], draw_last_pos[1], e.offsetX, e.offsetY, draw_color());
+    draw_last_pos = undefined;
+    rust_app_inst.end_edit();
+});
+
 document
     .getElementById("filechooser")
     .addEventListener("change", choose_new_file);
+document
+    .getElementById("pngchooser")
+    .addEventListener("change", choose_import_png);
+document
+    .getElementById("patchchooser")
+    .addEventListener("change", choose_patch);
+document
+    .getElementById("codecchooser")
+    .addEventListener("change", choose_codec_file);
@@ -0,0 +1,262 @@
+use crate::tile_codec::TileCodec;
+use crate::AbstractPixelTarget;
+
+/// Shifts everything drawn by a codec, so that single tiles can be placed anywhere
+struct OffsetPixelTarget<'a> {
+    inner: &'a mut dyn AbstractPixelTarget,
+    x: usize,
+    y: usize,
+}
+impl<'a> AbstractPixelTarget for OffsetPixelTarget<'a> {
+    fn draw_px_rgb(&mut self, x: usize, y: usize, r: u8, g: u8, b: u8) {
+        self.inner.draw_px_rgb(self.x + x, self.y + y, r, g, b);
+    }
+
+    fn draw_px_pal(&mut self, x: usize, y: usize, i: u8) {
+        self.inner.draw_px_pal(self.x + x, self.y + y, i);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TileOrder {
+    #[default]
+    RowMajor,
+    ColumnMajor,
+}
+
+/// Decides where each tile of the data ends up in the view.
+///
+/// Tiles are grouped into blocks of `block_w` x `block_h` tiles that are stored
+/// one after another, like GBA/NDS sprites in 1D mapping mode.
+/// `tile_order` is the order of the tiles inside a block,
+/// `block_order` the order of the blocks across the view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileArrangement {
+    pub block_w: usize,
+    pub block_h: usize,
+    pub tile_order: TileOrder,
+    pub block_order: TileOrder,
+}
+impl Default for TileArrangement {
+    fn default() -> Self {
+        Self::new(1, 1, TileOrder::RowMajor, TileOrder::RowMajor)
+    }
+}
+impl TileArrangement {
+    pub const fn new(
+        block_w: usize,
+        block_h: usize,
+        tile_order: TileOrder,
+        block_order: TileOrder,
+    ) -> Self {
+        Self {
+            block_w,
+            block_h,
+            tile_order,
+            block_order,
+        }
+    }
+
+    /// Whether tile `i` is simply shown at `(i % tiles_w, i / tiles_w)`
+    pub fn is_row_major(&self) -> bool {
+        self.block_w == 1 && self.block_h == 1 && self.block_order == TileOrder::RowMajor
+    }
+
+    fn tiles_per_block(&self) -> usize {
+        self.block_w * self.block_h
+    }
+
+    /// Number of tiles covered by one row of blocks (or column, if blocks are column-major)
+    /// in a view `tiles_w` x `tiles_h` tiles big.
+    /// Blocks cut off by the edge of the view still count in full.
+    pub fn tiles_per_line(&self, tiles_w: usize, tiles_h: usize) -> usize {
+        let blocks = match self.block_order {
+            TileOrder::RowMajor => tiles_w.div_ceil(self.block_w),
+            TileOrder::ColumnMajor => tiles_h.div_ceil(self.block_h),
+        };
+        blocks * self.tiles_per_block()
+    }
+
+    /// Index of the tile shown at (`tile_x`, `tile_y`) in a view `tiles_w` x `tiles_h` tiles big
+    pub fn tile_index(
+        &self,
+        tiles_w: usize,
+        tiles_h: usize,
+        tile_x: usize,
+        tile_y: usize,
+    ) -> usize {
+        let block_x = tile_x / self.block_w;
+        let block_y = tile_y / self.block_h;
+        let x = tile_x % self.block_w;
+        let y = tile_y % self.block_h;
+
+        let in_block = match self.tile_order {
+            TileOrder::RowMajor => y * self.block_w + x,
+            TileOrder::ColumnMajor => x * self.block_h + y,
+        };
+        let block_line = match self.block_order {
+            TileOrder::RowMajor => block_y * self.tiles_per_line(tiles_w, tiles_h),
+            TileOrder::ColumnMajor => block_x * self.tiles_per_line(tiles_w, tiles_h),
+        };
+        let block_in_line = match self.block_order {
+            TileOrder::RowMajor => block_x,
+            TileOrder::ColumnMajor => block_y,
+        };
+        block_line + block_in_line * self.tiles_per_block() + in_block
+    }
+
+    /// Bit offset of the tile shown at (`tile_x`, `tile_y`), relative to the start of the view
+    pub fn tile_bit_offset(
+        &self,
+        codec: &dyn TileCodec,
+        tiles_w: usize,
+        tiles_h: usize,
+        tile_x: usize,
+        tile_y: usize,
+    ) -> usize {
+        self.tile_index(tiles_w, tiles_h, tile_x, tile_y) * codec.bits_per_tile()
+    }
+
+    /// Like [`TileCodec::render`], but placing tiles according to this arrangement
+    pub fn render(
+        &self,
+        codec: &dyn TileCodec,
+        r: &mut dyn AbstractPixelTarget,
+        bytes: &[u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        tiles_h: usize,
+    ) {
+        if self.is_row_major() {
+            codec.render(r, bytes, bit_offs, tiles_w, tiles_h);
+            return;
+        }
+
+        let tile_w = codec.tile_width();
+        let tile_h = codec.tile_height();
+        for tile_y in 0..tiles_h {
+            for tile_x in 0..tiles_w {
+                let bit_off = bit_offs as usize
+                    + self.tile_bit_offset(codec, tiles_w, tiles_h, tile_x, tile_y);
+                if bit_off >= bytes.len() * 8 {
+                    continue;
+                }
+                codec.render(
+                    &mut OffsetPixelTarget {
+                        inner: r,
+                        x: tile_x * tile_w,
+                        y: tile_y * tile_h,
+                    },
+                    &bytes[(bit_off / 8)..],
+                    (bit_off % 8) as u8,
+                    1,
+                    1,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile_codec::{GenesisGraphics4bpp, NESGraphics};
+
+    fn view_order(a: &TileArrangement, tiles_w: usize, tiles_h: usize) -> Vec<usize> {
+        (0..tiles_h)
+            .flat_map(|y| (0..tiles_w).map(move |x| a.tile_index(tiles_w, tiles_h, x, y)))
+            .collect()
+    }
+
+    #[test]
+    fn row_major() {
+        let a = TileArrangement::default();
+        assert!(a.is_row_major());
+        assert_eq!(view_order(&a, 4, 2), [0, 1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn column_major() {
+        let a = TileArrangement::new(1, 1, TileOrder::RowMajor, TileOrder::ColumnMajor);
+        assert!(!a.is_row_major());
+        assert_eq!(view_order(&a, 4, 2), [0, 2, 4, 6, 1, 3, 5, 7]);
+    }
+
+    #[test]
+    fn sprites_1d() {
+        // 16x16 px sprites, two per row
+        let a = TileArrangement::new(2, 2, TileOrder::RowMajor, TileOrder::RowMajor);
+        assert_eq!(a.tiles_per_line(4, 4), 8);
+        assert_eq!(
+            view_order(&a, 4, 4),
+            [0, 1, 4, 5, 2, 3, 6, 7, 8, 9, 12, 13, 10, 11, 14, 15]
+        );
+
+        // a partial block at the right edge still reserves all its tiles
+        let a = TileArrangement::new(4, 2, TileOrder::RowMajor, TileOrder::RowMajor);
+        assert_eq!(a.tile_index(6, 2, 5, 1), 13);
+        assert_eq!(a.tile_index(6, 4, 0, 2), 16);
+        assert_eq!(a.tile_bit_offset(&NESGraphics::new(), 6, 2, 5, 1), 13 * 128);
+    }
+
+    #[test]
+    fn nes_8x16() {
+        let a = TileArrangement::new(1, 2, TileOrder::RowMajor, TileOrder::RowMajor);
+        assert_eq!(view_order(&a, 3, 4), [0, 2, 4, 1, 3, 5, 6, 8, 10, 7, 9, 11]);
+
+        // tile i has its top left pixel set to color 1 + i % 3
+        let mut data = vec![0u8; 16 * 4];
+        for i in 0..4 {
+            let c = 1 + i % 3;
+            data[i * 16] = (c & 1) as u8 * 0x80;
+            data[i * 16 + 8] = (c >> 1) as u8 * 0x80;
+        }
+        let (w, h) = (16, 32);
+        let mut px = vec![0u8; w * h];
+        a.render(
+            &NESGraphics::new(),
+            &mut crate::InMemoryPixelWriter { w, px: &mut px },
+            &data,
+            0,
+            2,
+            2,
+        );
+        assert_eq!(px[0], 1);
+        assert_eq!(px[8 * w], 2);
+        assert_eq!(px[8], 3);
+        assert_eq!(px[8 * w + 8], 1);
+    }
+
+    #[test]
+    fn genesis_sprites() {
+        // 2x3 tile sprites, tiles stored column by column
+        let a = TileArrangement::new(2, 3, TileOrder::ColumnMajor, TileOrder::RowMajor);
+        assert_eq!(view_order(&a, 2, 3), [0, 3, 1, 4, 2, 5]);
+
+        // tile i is filled with color i, the second sprite with 6..12
+        let data: Vec<u8> = (0..12).flat_map(|i| [i * 0x11; 32]).collect();
+        let (w, h) = (32, 24);
+        let mut px = vec![0u8; w * h];
+        a.render(
+            &GenesisGraphics4bpp::new(),
+            &mut crate::InMemoryPixelWriter { w, px: &mut px },
+            &data,
+            0,
+            4,
+            3,
+        );
+        let tile_at = |tile_x: usize, tile_y: usize| px[tile_y * 8 * w + tile_x * 8];
+        assert_eq!([0, 1, 2, 3].map(|x| tile_at(x, 0)), [0, 3, 6, 9]);
+        assert_eq!([0, 1, 2, 3].map(|x| tile_at(x, 2)), [2, 5, 8, 11]);
+    }
+
+    #[test]
+    fn column_major_blocks() {
+        let a = TileArrangement::new(2, 2, TileOrder::ColumnMajor, TileOrder::RowMajor);
+        assert_eq!(view_order(&a, 4, 2), [0, 2, 4, 6, 1, 3, 5, 7]);
+
+        let a = TileArrangement::new(2, 1, TileOrder::ColumnMajor, TileOrder::ColumnMajor);
+        assert_eq!(a.tiles_per_line(4, 3), 6);
+        assert_eq!(view_order(&a, 4, 3), [0, 1, 6, 7, 2, 3, 8, 9, 4, 5, 10, 11]);
+    }
+}
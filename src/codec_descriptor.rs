@@ -0,0 +1,516 @@
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+use crate::linear_codec::LinearCodec;
+use crate::tile_codec::TileCodec;
+use crate::AbstractPixelTarget;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitOrderKind {
+    MsbFirst,
+    LsbFirst,
+}
+
+pub const MAX_TILE_SIZE: usize = 256;
+pub const MAX_PAD_BITS: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DescriptorError {
+    BadBpp(usize),
+    ZeroTileSize,
+    TileTooLarge,
+    PadTooLarge(&'static str),
+    ZeroPlanesPerGroup,
+    PlanarLinear,
+}
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DescriptorError::BadBpp(bpp) => {
+                write!(f, "{} bits per pixel is not supported (must be 1-8)", bpp)
+            }
+            DescriptorError::ZeroTileSize => write!(f, "tiles must be at least 1x1 px"),
+            DescriptorError::TileTooLarge => write!(
+                f,
+                "tiles can be at most {} x {} px",
+                MAX_TILE_SIZE, MAX_TILE_SIZE
+            ),
+            DescriptorError::PadTooLarge(name) => {
+                write!(f, "{} can be at most {} bits", name, MAX_PAD_BITS)
+            }
+            DescriptorError::ZeroPlanesPerGroup => {
+                write!(f, "planar codecs need at least 1 plane per group")
+            }
+            DescriptorError::PlanarLinear => write!(f, "non-tiled codecs cannot be planar"),
+        }
+    }
+}
+impl std::error::Error for DescriptorError {}
+
+/// A codec assembled at runtime rather than from const generics.
+///
+/// Packed pixels store their `bpp` bits next to each other, followed by `px_pad` bits.
+/// Planar pixels are split into planes, which are stored `planes_per_group` at a time:
+/// every row of every plane in a group, then `group_pad` bits, then the next group.
+/// A single plane per group is the NES layout, all planes in one group is the GB/SMS layout.
+/// Rows are followed by `row_pad` bits and tiles by `final_pad` bits.
+///
+/// `data_bit_order` is the order bits are read from each byte,
+/// `pixel_bit_order` says whether the first bit (or plane) read is the pixel's MSB or LSB.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CodecDescriptor {
+    pub tiled: bool,
+    pub bpp: usize,
+    pub planar: bool,
+    pub planes_per_group: usize,
+    pub data_bit_order: BitOrderKind,
+    pub pixel_bit_order: BitOrderKind,
+    pub tile_w: usize,
+    pub tile_h: usize,
+    pub px_pad: usize,
+    pub row_pad: usize,
+    pub group_pad: usize,
+    pub final_pad: usize,
+}
+impl Default for CodecDescriptor {
+    fn default() -> Self {
+        Self {
+            tiled: true,
+            bpp: 1,
+            planar: false,
+            planes_per_group: 1,
+            data_bit_order: BitOrderKind::MsbFirst,
+            pixel_bit_order: BitOrderKind::LsbFirst,
+            tile_w: 8,
+            tile_h: 8,
+            px_pad: 0,
+            row_pad: 0,
+            group_pad: 0,
+            final_pad: 0,
+        }
+    }
+}
+#[wasm_bindgen]
+impl CodecDescriptor {
+    /// Starts out as 1bpp packed 8x8 tiles, to be filled in field by field
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+impl CodecDescriptor {
+    pub fn validate(&self) -> Result<(), DescriptorError> {
+        if !(1..=8).contains(&self.bpp) {
+            return Err(DescriptorError::BadBpp(self.bpp));
+        }
+        if self.tiled && (self.tile_w == 0 || self.tile_h == 0) {
+            return Err(DescriptorError::ZeroTileSize);
+        }
+        // keeps bit offsets from overflowing, and canvases a sane size
+        if self.tiled && (self.tile_w > MAX_TILE_SIZE || self.tile_h > MAX_TILE_SIZE) {
+            return Err(DescriptorError::TileTooLarge);
+        }
+        for (name, pad) in [
+            ("px_pad", self.px_pad),
+            ("row_pad", self.row_pad),
+            ("group_pad", self.group_pad),
+            ("final_pad", self.final_pad),
+        ] {
+            if pad > MAX_PAD_BITS {
+                return Err(DescriptorError::PadTooLarge(name));
+            }
+        }
+        if self.planar && self.planes_per_group == 0 {
+            return Err(DescriptorError::ZeroPlanesPerGroup);
+        }
+        if self.planar && !self.tiled {
+            return Err(DescriptorError::PlanarLinear);
+        }
+        Ok(())
+    }
+
+    fn planes_in_group(&self, group: usize) -> usize {
+        (self.bpp - group * self.planes_per_group).min(self.planes_per_group)
+    }
+
+    fn bits_per_group(&self, group: usize) -> usize {
+        (self.tile_w + self.row_pad) * self.planes_in_group(group) * self.tile_h + self.group_pad
+    }
+
+    fn bits_per_packed_row(&self, w: usize) -> usize {
+        (self.bpp + self.px_pad) * w + self.row_pad
+    }
+
+    /// Position of the `b`th bit (or plane) of pixel (`x`, `y`), relative to the start of a tile
+    fn tile_bit_idx(&self, x: usize, y: usize, b: usize) -> usize {
+        if self.planar {
+            let group = b / self.planes_per_group;
+            let bits_per_plane_row = self.tile_w + self.row_pad;
+            // all groups before this one are full
+            group * self.bits_per_group(0)
+                + y * bits_per_plane_row * self.planes_in_group(group)
+                + (b % self.planes_per_group) * bits_per_plane_row
+                + x
+        } else {
+            y * self.bits_per_packed_row(self.tile_w) + x * (self.bpp + self.px_pad) + b
+        }
+    }
+
+    fn pixel_bit(&self, b: usize) -> usize {
+        match self.pixel_bit_order {
+            BitOrderKind::LsbFirst => b,
+            BitOrderKind::MsbFirst => self.bpp - 1 - b,
+        }
+    }
+
+    fn data_bit_mask(&self, bit_idx: usize) -> u8 {
+        match self.data_bit_order {
+            BitOrderKind::MsbFirst => 0x80 >> (bit_idx % 8),
+            BitOrderKind::LsbFirst => 1 << (bit_idx % 8),
+        }
+    }
+
+    /// Reads a pixel whose `b`th bit is at `base + bit_idx(b)`, or `None` past the end of data
+    fn read_px(&self, bytes: &[u8], base: usize, bit_idx: impl Fn(usize) -> usize) -> Option<u8> {
+        let mut px = 0;
+        for b in 0..self.bpp {
+            let idx = base + bit_idx(b);
+            let byte = bytes.get(idx / 8)?;
+            if byte & self.data_bit_mask(idx) != 0 {
+                px |= 1 << self.pixel_bit(b);
+            }
+        }
+        Some(px)
+    }
+
+    fn store_px(
+        &self,
+        bytes: &mut [u8],
+        base: usize,
+        bit_idx: impl Fn(usize) -> usize,
+        i: u8,
+    ) -> bool {
+        // check every bit first, so a pixel cut off by the end of the data isn't half written
+        if (0..self.bpp).any(|b| (base + bit_idx(b)) / 8 >= bytes.len()) {
+            return false;
+        }
+        for b in 0..self.bpp {
+            let idx = base + bit_idx(b);
+            let mask = self.data_bit_mask(idx);
+            let byte = &mut bytes[idx / 8];
+            if i & (1 << self.pixel_bit(b)) != 0 {
+                *byte |= mask;
+            } else {
+                *byte &= !mask;
+            }
+        }
+        true
+    }
+}
+
+impl TileCodec for CodecDescriptor {
+    fn num_palette_colors(&self) -> usize {
+        1 << self.bpp
+    }
+
+    fn bits_per_tile(&self) -> usize {
+        if self.planar {
+            let groups = self.bpp.div_ceil(self.planes_per_group);
+            (0..groups).map(|g| self.bits_per_group(g)).sum::<usize>() + self.final_pad
+        } else {
+            self.bits_per_packed_row(self.tile_w) * self.tile_h + self.final_pad
+        }
+    }
+
+    fn tile_width(&self) -> usize {
+        self.tile_w
+    }
+
+    fn tile_height(&self) -> usize {
+        self.tile_h
+    }
+
+    fn render(
+        &self,
+        r: &mut dyn AbstractPixelTarget,
+        bytes: &[u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        tiles_h: usize,
+    ) {
+        let data_bits_per_tile = TileCodec::bits_per_tile(self);
+
+        for tile_y in 0..tiles_h {
+            for tile_x in 0..tiles_w {
+                let tile_i = tile_y * tiles_w + tile_x;
+                let base = bit_offs as usize + tile_i * data_bits_per_tile;
+                for px_y in 0..self.tile_h {
+                    for px_x in 0..self.tile_w {
+                        let Some(px) =
+                            self.read_px(bytes, base, |b| self.tile_bit_idx(px_x, px_y, b))
+                        else {
+                            return;
+                        };
+                        r.draw_px_pal(tile_x * self.tile_w + px_x, tile_y * self.tile_h + px_y, px);
+                    }
+                }
+            }
+        }
+    }
+
+    fn write_px(
+        &self,
+        bytes: &mut [u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        x: usize,
+        y: usize,
+        i: u8,
+    ) -> bool {
+        let tile_i = (y / self.tile_h) * tiles_w + x / self.tile_w;
+        let base = bit_offs as usize + tile_i * TileCodec::bits_per_tile(self);
+        let (px_x, px_y) = (x % self.tile_w, y % self.tile_h);
+        self.store_px(bytes, base, |b| self.tile_bit_idx(px_x, px_y, b), i)
+    }
+}
+
+impl LinearCodec for CodecDescriptor {
+    fn num_palette_colors(&self) -> usize {
+        1 << self.bpp
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.bpp + self.px_pad
+    }
+
+    fn bits_per_row(&self, w: usize) -> usize {
+        self.bits_per_packed_row(w)
+    }
+
+    fn render(
+        &self,
+        r: &mut dyn AbstractPixelTarget,
+        bytes: &[u8],
+        bit_offs: u8,
+        w: usize,
+        h: usize,
+    ) {
+        for y in 0..h {
+            for x in 0..w {
+                let base = bit_offs as usize + y * self.bits_per_packed_row(w);
+                let Some(px) = self.read_px(bytes, base, |b| x * (self.bpp + self.px_pad) + b)
+                else {
+                    return;
+                };
+                r.draw_px_pal(x, y, px);
+            }
+        }
+    }
+
+    fn write_px(
+        &self,
+        bytes: &mut [u8],
+        bit_offs: u8,
+        w: usize,
+        x: usize,
+        y: usize,
+        i: u8,
+    ) -> bool {
+        let base = bit_offs as usize + y * self.bits_per_packed_row(w);
+        self.store_px(bytes, base, |b| x * (self.bpp + self.px_pad) + b, i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear_codec::_1bppLsbFirstGraphics;
+    use crate::tile_codec::tests::{check_write_past_end, render_tiles};
+    use crate::tile_codec::*;
+
+    // a descriptor must see exactly what the equivalent builtin codec sees
+    fn assert_same(desc: &CodecDescriptor, builtin: &dyn TileCodec, bit_offs: u8) {
+        desc.validate().unwrap();
+        assert_eq!(TileCodec::bits_per_tile(desc), builtin.bits_per_tile());
+        let data: Vec<u8> = (0..512u32).map(|i| (i * 0x9d + (i >> 3)) as u8).collect();
+        assert_eq!(
+            render_tiles(desc, &data, bit_offs, 2, 2),
+            render_tiles(builtin, &data, bit_offs, 2, 2)
+        );
+    }
+
+    #[test]
+    fn matches_builtins() {
+        let nes = CodecDescriptor {
+            bpp: 2,
+            planar: true,
+            planes_per_group: 1,
+            ..Default::default()
+        };
+        assert_same(&nes, &NESGraphics::new(), 3);
+
+        let sms = CodecDescriptor {
+            bpp: 4,
+            planar: true,
+            planes_per_group: 4,
+            ..Default::default()
+        };
+        assert_same(&sms, &SMSGraphics4bpp::new(), 3);
+
+        let snes3 = CodecDescriptor {
+            bpp: 3,
+            planar: true,
+            planes_per_group: 2,
+            ..Default::default()
+        };
+        assert_same(&snes3, &SNESGraphics3bpp::new(), 3);
+
+        let gba = CodecDescriptor {
+            bpp: 4,
+            data_bit_order: BitOrderKind::LsbFirst,
+            ..Default::default()
+        };
+        assert_same(&gba, &GBATileGraphics4bpp::new(), 3);
+
+        let genesis = CodecDescriptor {
+            bpp: 4,
+            pixel_bit_order: BitOrderKind::MsbFirst,
+            ..Default::default()
+        };
+        // HiLo only agrees with MSB-first data on byte boundaries
+        assert_same(&genesis, &GenesisGraphics4bpp::new(), 0);
+    }
+
+    #[test]
+    fn odd_sizes_roundtrip() {
+        let desc = CodecDescriptor {
+            bpp: 5,
+            tile_w: 12,
+            tile_h: 12,
+            px_pad: 1,
+            row_pad: 3,
+            final_pad: 7,
+            ..Default::default()
+        };
+        desc.validate().unwrap();
+        let planar = CodecDescriptor {
+            planar: true,
+            planes_per_group: 2,
+            group_pad: 5,
+            px_pad: 0,
+            ..desc
+        };
+
+        for codec in [&desc as &dyn TileCodec, &planar] {
+            let mut data = vec![0u8; codec.bits_per_tile() * 4 / 8 + 1];
+            let w = 2 * codec.tile_width();
+            let mut expected = vec![0u8; w * codec.tile_height() * 2];
+            for (i, c) in expected.iter_mut().enumerate() {
+                *c = (i * 7 % 32) as u8;
+                codec.write_px(&mut data, 3, 2, i % w, i / w, *c);
+            }
+            assert_eq!(render_tiles(codec, &data, 3, 2, 2), expected);
+            check_write_past_end(codec);
+        }
+    }
+
+    #[test]
+    fn linear() {
+        let desc = CodecDescriptor {
+            tiled: false,
+            data_bit_order: BitOrderKind::LsbFirst,
+            ..Default::default()
+        };
+        let data = [0x5a, 0x0f];
+        let mut expected = [0u8; 16];
+        _1bppLsbFirstGraphics::new().render(
+            &mut crate::InMemoryPixelWriter {
+                w: 4,
+                px: &mut expected,
+            },
+            &data,
+            0,
+            4,
+            4,
+        );
+        let mut px = [0u8; 16];
+        LinearCodec::render(
+            &desc,
+            &mut crate::InMemoryPixelWriter { w: 4, px: &mut px },
+            &data,
+            0,
+            4,
+            4,
+        );
+        assert_eq!(px, expected);
+    }
+
+    #[test]
+    fn validate() {
+        let bad = [
+            (
+                CodecDescriptor {
+                    bpp: 9,
+                    ..Default::default()
+                },
+                DescriptorError::BadBpp(9),
+            ),
+            (
+                CodecDescriptor {
+                    tile_w: 0,
+                    ..Default::default()
+                },
+                DescriptorError::ZeroTileSize,
+            ),
+            (
+                CodecDescriptor {
+                    tile_h: MAX_TILE_SIZE + 1,
+                    ..Default::default()
+                },
+                DescriptorError::TileTooLarge,
+            ),
+            (
+                // a negative number from JS
+                CodecDescriptor {
+                    row_pad: u32::MAX as usize,
+                    ..Default::default()
+                },
+                DescriptorError::PadTooLarge("row_pad"),
+            ),
+            (
+                CodecDescriptor {
+                    planar: true,
+                    planes_per_group: 0,
+                    ..Default::default()
+                },
+                DescriptorError::ZeroPlanesPerGroup,
+            ),
+            (
+                CodecDescriptor {
+                    tiled: false,
+                    planar: true,
+                    ..Default::default()
+                },
+                DescriptorError::PlanarLinear,
+            ),
+        ];
+        for (desc, err) in bad {
+            assert_eq!(desc.validate(), Err(err));
+        }
+
+        let biggest = CodecDescriptor {
+            bpp: 8,
+            tile_w: MAX_TILE_SIZE,
+            tile_h: MAX_TILE_SIZE,
+            px_pad: MAX_PAD_BITS,
+            row_pad: MAX_PAD_BITS,
+            group_pad: MAX_PAD_BITS,
+            final_pad: MAX_PAD_BITS,
+            ..Default::default()
+        };
+        assert_eq!(biggest.validate(), Ok(()));
+    }
+}
@@ -0,0 +1,300 @@
+use std::fmt;
+
+use crate::codec_descriptor::{BitOrderKind, CodecDescriptor, DescriptorError};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CodecFileError {
+    Syntax {
+        line: usize,
+    },
+    NoName {
+        line: usize,
+    },
+    UnknownKey {
+        line: usize,
+        key: String,
+    },
+    BadValue {
+        line: usize,
+        key: String,
+        value: String,
+    },
+    Invalid {
+        name: String,
+        err: DescriptorError,
+    },
+}
+impl fmt::Display for CodecFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecFileError::Syntax { line } => {
+                write!(f, "line {}: expected `[name]` or `key = value`", line)
+            }
+            CodecFileError::NoName { line } => {
+                write!(f, "line {}: settings must come after a `[name]` line", line)
+            }
+            CodecFileError::UnknownKey { line, key } => {
+                write!(f, "line {}: unknown setting `{}`", line, key)
+            }
+            CodecFileError::BadValue { line, key, value } => {
+                write!(
+                    f,
+                    "line {}: `{}` is not a valid value for `{}`",
+                    line, value, key
+                )
+            }
+            CodecFileError::Invalid { name, err } => write!(f, "codec `{}`: {}", name, err),
+        }
+    }
+}
+impl std::error::Error for CodecFileError {}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_bit_order(value: &str) -> Option<BitOrderKind> {
+    match value {
+        "msb" => Some(BitOrderKind::MsbFirst),
+        "lsb" => Some(BitOrderKind::LsbFirst),
+        _ => None,
+    }
+}
+
+fn bit_order_str(order: BitOrderKind) -> &'static str {
+    match order {
+        BitOrderKind::MsbFirst => "msb",
+        BitOrderKind::LsbFirst => "lsb",
+    }
+}
+
+fn set_key(
+    desc: &mut CodecDescriptor,
+    line: usize,
+    key: &str,
+    value: &str,
+) -> Result<(), CodecFileError> {
+    let bad_value = || CodecFileError::BadValue {
+        line,
+        key: key.to_owned(),
+        value: value.to_owned(),
+    };
+    let num = || value.parse().map_err(|_| bad_value());
+    let flag = || parse_bool(value).ok_or_else(bad_value);
+    let order = || parse_bit_order(value).ok_or_else(bad_value);
+
+    match key {
+        "tiled" => desc.tiled = flag()?,
+        "bpp" => desc.bpp = num()?,
+        "planar" => desc.planar = flag()?,
+        "planes_per_group" => desc.planes_per_group = num()?,
+        "data_bit_order" => desc.data_bit_order = order()?,
+        "pixel_bit_order" => desc.pixel_bit_order = order()?,
+        "tile_w" => desc.tile_w = num()?,
+        "tile_h" => desc.tile_h = num()?,
+        "px_pad" => desc.px_pad = num()?,
+        "row_pad" => desc.row_pad = num()?,
+        "group_pad" => desc.group_pad = num()?,
+        "final_pad" => desc.final_pad = num()?,
+        _ => {
+            return Err(CodecFileError::UnknownKey {
+                line,
+                key: key.to_owned(),
+            })
+        }
+    }
+    Ok(())
+}
+
+/// Parses a codec definition file.
+///
+/// Every codec starts with a `[name]` line, followed by `key = value` lines named after the
+/// fields of [`CodecDescriptor`]. Keys that are left out keep their default value.
+/// Bit orders are `msb` or `lsb`, flags are `true` or `false`.
+/// Everything after a `#` is a comment, except in names.
+///
+/// ```text
+/// # 5bpp, one plane after another
+/// [Some arcade board]
+/// bpp = 5
+/// planar = true
+/// tile_w = 16
+/// tile_h = 16
+/// ```
+pub fn parse_codecs(text: &str) -> Result<Vec<(String, CodecDescriptor)>, CodecFileError> {
+    let mut ret: Vec<(String, CodecDescriptor)> = Vec::new();
+
+    for (line_i, line) in text.lines().enumerate() {
+        let line_no = line_i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            ret.push((name.trim().to_owned(), CodecDescriptor::default()));
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or(CodecFileError::Syntax { line: line_no })?;
+        let value = value.split_once('#').map_or(value, |(v, _)| v);
+        let (key, value) = (key.trim(), value.trim());
+        let (_, desc) = ret
+            .last_mut()
+            .ok_or(CodecFileError::NoName { line: line_no })?;
+        set_key(desc, line_no, key, value)?;
+    }
+
+    for (name, desc) in &ret {
+        desc.validate().map_err(|err| CodecFileError::Invalid {
+            name: name.clone(),
+            err,
+        })?;
+    }
+    Ok(ret)
+}
+
+/// Writes a codec in the format read by [`parse_codecs`], spelling out every key
+pub fn format_codec(name: &str, desc: &CodecDescriptor) -> String {
+    format!(
+        "[{}]\n\
+         tiled = {}\n\
+         bpp = {}\n\
+         planar = {}\n\
+         planes_per_group = {}\n\
+         data_bit_order = {}\n\
+         pixel_bit_order = {}\n\
+         tile_w = {}\n\
+         tile_h = {}\n\
+         px_pad = {}\n\
+         row_pad = {}\n\
+         group_pad = {}\n\
+         final_pad = {}\n",
+        name,
+        desc.tiled,
+        desc.bpp,
+        desc.planar,
+        desc.planes_per_group,
+        bit_order_str(desc.data_bit_order),
+        bit_order_str(desc.pixel_bit_order),
+        desc.tile_w,
+        desc.tile_h,
+        desc.px_pad,
+        desc.row_pad,
+        desc.group_pad,
+        desc.final_pad,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let text = "
+            # comment
+            [A]
+            bpp = 5
+            planar = true
+            tile_w=16 # trailing comment
+
+            [ B ]
+            tiled = false
+            data_bit_order = lsb
+        ";
+        let codecs = parse_codecs(text).unwrap();
+        assert_eq!(codecs.len(), 2);
+        assert_eq!(codecs[0].0, "A");
+        assert_eq!(
+            codecs[0].1,
+            CodecDescriptor {
+                bpp: 5,
+                planar: true,
+                tile_w: 16,
+                ..Default::default()
+            }
+        );
+        assert_eq!(codecs[1].0, "B");
+        assert_eq!(
+            codecs[1].1,
+            CodecDescriptor {
+                tiled: false,
+                data_bit_order: BitOrderKind::LsbFirst,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn roundtrip() {
+        let desc = CodecDescriptor {
+            bpp: 3,
+            planar: true,
+            planes_per_group: 2,
+            pixel_bit_order: BitOrderKind::MsbFirst,
+            tile_w: 12,
+            tile_h: 10,
+            px_pad: 1,
+            row_pad: 2,
+            group_pad: 3,
+            final_pad: 4,
+            ..Default::default()
+        };
+        let text = format_codec("odd one", &desc) + &format_codec("default", &Default::default());
+        assert_eq!(
+            parse_codecs(&text).unwrap(),
+            [
+                ("odd one".to_owned(), desc),
+                ("default".to_owned(), Default::default())
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse_codecs("[a]\nbpp 4"),
+            Err(CodecFileError::Syntax { line: 2 })
+        );
+        assert_eq!(
+            parse_codecs("bpp = 4"),
+            Err(CodecFileError::NoName { line: 1 })
+        );
+        assert_eq!(
+            parse_codecs("[a]\ncolors = 16"),
+            Err(CodecFileError::UnknownKey {
+                line: 2,
+                key: "colors".to_owned()
+            })
+        );
+        assert_eq!(
+            parse_codecs("[a]\n\nplanar = yes"),
+            Err(CodecFileError::BadValue {
+                line: 3,
+                key: "planar".to_owned(),
+                value: "yes".to_owned()
+            })
+        );
+        assert_eq!(
+            parse_codecs("[a]\nbpp = 12"),
+            Err(CodecFileError::Invalid {
+                name: "a".to_owned(),
+                err: DescriptorError::BadBpp(12)
+            })
+        );
+        assert_eq!(
+            parse_codecs("[a]\nfinal_pad = 100000"),
+            Err(CodecFileError::Invalid {
+                name: "a".to_owned(),
+                err: DescriptorError::PadTooLarge("final_pad")
+            })
+        );
+    }
+}
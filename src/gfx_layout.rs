@@ -0,0 +1,337 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::tile_codec::TileCodec;
+use crate::AbstractPixelTarget;
+
+/// Offsets reach at most 64 MiB into the data, which keeps three of them added together
+/// well within a 32-bit usize
+pub const MAX_OFFSET_BITS: usize = 1 << 29;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GfxLayoutError {
+    BadPlanes(usize),
+    ZeroSize,
+    ZeroCharIncrement,
+    OffsetTooLarge,
+    WrongXOffsets { expected: usize, got: usize },
+    WrongYOffsets { expected: usize, got: usize },
+}
+impl fmt::Display for GfxLayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GfxLayoutError::BadPlanes(planes) => {
+                write!(f, "{} planes are not supported (must be 1-8)", planes)
+            }
+            GfxLayoutError::ZeroSize => write!(f, "tiles must be at least 1x1 px"),
+            GfxLayoutError::ZeroCharIncrement => write!(f, "tiles must be at least 1 bit apart"),
+            GfxLayoutError::OffsetTooLarge => {
+                write!(f, "offsets can be at most {} bits", MAX_OFFSET_BITS)
+            }
+            GfxLayoutError::WrongXOffsets { expected, got } => {
+                write!(f, "expected {} x offsets, got {}", expected, got)
+            }
+            GfxLayoutError::WrongYOffsets { expected, got } => {
+                write!(f, "expected {} y offsets, got {}", expected, got)
+            }
+        }
+    }
+}
+impl std::error::Error for GfxLayoutError {}
+
+/// A codec driven by explicit bit offset tables, like MAME's `gfx_layout`.
+///
+/// Bit `n` of the data is bit `7 - n % 8` of byte `n / 8`.
+/// Pixel (x, y) of tile i takes its bits from
+/// `i * char_increment + plane_offsets[p] + x_offsets[x] + y_offsets[y]`,
+/// with `plane_offsets[0]` being the MSB of the pixel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GfxLayout {
+    width: usize,
+    height: usize,
+    plane_offsets: Vec<usize>,
+    x_offsets: Vec<usize>,
+    y_offsets: Vec<usize>,
+    char_increment: usize,
+}
+impl GfxLayout {
+    pub fn new(
+        width: usize,
+        height: usize,
+        plane_offsets: Vec<usize>,
+        x_offsets: Vec<usize>,
+        y_offsets: Vec<usize>,
+        char_increment: usize,
+    ) -> Result<Self, GfxLayoutError> {
+        if !(1..=8).contains(&plane_offsets.len()) {
+            return Err(GfxLayoutError::BadPlanes(plane_offsets.len()));
+        }
+        if width == 0 || height == 0 {
+            return Err(GfxLayoutError::ZeroSize);
+        }
+        if x_offsets.len() != width {
+            return Err(GfxLayoutError::WrongXOffsets {
+                expected: width,
+                got: x_offsets.len(),
+            });
+        }
+        if y_offsets.len() != height {
+            return Err(GfxLayoutError::WrongYOffsets {
+                expected: height,
+                got: y_offsets.len(),
+            });
+        }
+        if char_increment == 0 {
+            return Err(GfxLayoutError::ZeroCharIncrement);
+        }
+        let offsets = [&plane_offsets, &x_offsets, &y_offsets];
+        if char_increment > MAX_OFFSET_BITS
+            || offsets
+                .iter()
+                .any(|o| o.iter().any(|&o| o > MAX_OFFSET_BITS))
+        {
+            return Err(GfxLayoutError::OffsetTooLarge);
+        }
+        Ok(Self {
+            width,
+            height,
+            plane_offsets,
+            x_offsets,
+            y_offsets,
+            char_increment,
+        })
+    }
+
+    /// The first bit of tile `tile_i`, or `None` if it can't be addressed at all
+    fn tile_base(&self, bit_offs: u8, tile_i: usize) -> Option<usize> {
+        tile_i
+            .checked_mul(self.char_increment)?
+            .checked_add(bit_offs as usize)
+    }
+
+    fn bit_idx(&self, base: usize, plane: usize, x: usize, y: usize) -> Option<usize> {
+        base.checked_add(self.plane_offsets[plane] + self.x_offsets[x] + self.y_offsets[y])
+    }
+
+    fn pixel_bit(&self, plane: usize) -> usize {
+        self.plane_offsets.len() - 1 - plane
+    }
+}
+
+impl TileCodec for GfxLayout {
+    fn num_palette_colors(&self) -> usize {
+        1 << self.plane_offsets.len()
+    }
+
+    fn bits_per_tile(&self) -> usize {
+        self.char_increment
+    }
+
+    fn tile_width(&self) -> usize {
+        self.width
+    }
+
+    fn tile_height(&self) -> usize {
+        self.height
+    }
+
+    fn tile_bit_ranges(&self, tile_i: usize) -> Vec<Range<usize>> {
+        // planes can be far apart (e.g. in different halves of the ROM), so cover each separately
+        let min_xy = self.x_offsets.iter().min().unwrap() + self.y_offsets.iter().min().unwrap();
+        let max_xy = self.x_offsets.iter().max().unwrap() + self.y_offsets.iter().max().unwrap();
+        // saturates for tiles that can't be addressed, which are past the end of any data
+        let base = tile_i.saturating_mul(self.char_increment);
+        self.plane_offsets
+            .iter()
+            .map(|p| base.saturating_add(p + min_xy)..base.saturating_add(p + max_xy + 1))
+            .collect()
+    }
+
+    fn render(
+        &self,
+        r: &mut dyn AbstractPixelTarget,
+        bytes: &[u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        tiles_h: usize,
+    ) {
+        for tile_y in 0..tiles_h {
+            for tile_x in 0..tiles_w {
+                let tile_i = tile_y * tiles_w + tile_x;
+                let Some(base) = self.tile_base(bit_offs, tile_i) else {
+                    return;
+                };
+                for px_y in 0..self.height {
+                    'px: for px_x in 0..self.width {
+                        let mut px = 0;
+                        for plane in 0..self.plane_offsets.len() {
+                            // planes can be spread out, so later pixels may still be in range
+                            let Some((bit_idx, byte)) = self
+                                .bit_idx(base, plane, px_x, px_y)
+                                .and_then(|b| Some((b, bytes.get(b / 8)?)))
+                            else {
+                                continue 'px;
+                            };
+                            if byte & (0x80 >> (bit_idx % 8)) != 0 {
+                                px |= 1 << self.pixel_bit(plane);
+                            }
+                        }
+
+                        r.draw_px_pal(tile_x * self.width + px_x, tile_y * self.height + px_y, px);
+                    }
+                }
+            }
+        }
+    }
+
+    fn write_px(
+        &self,
+        bytes: &mut [u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        x: usize,
+        y: usize,
+        i: u8,
+    ) -> bool {
+        let tile_i = (y / self.height) * tiles_w + x / self.width;
+        let Some(base) = self.tile_base(bit_offs, tile_i) else {
+            return false;
+        };
+        let bit_idx = |plane| self.bit_idx(base, plane, x % self.width, y % self.height);
+        // the planes can be far apart, so make sure all of them fit before writing any
+        let in_range = |plane| bit_idx(plane).is_some_and(|b| b / 8 < bytes.len());
+        if !(0..self.plane_offsets.len()).all(in_range) {
+            return false;
+        }
+        for plane in 0..self.plane_offsets.len() {
+            let bit_idx = bit_idx(plane).unwrap();
+            let byte = &mut bytes[bit_idx / 8];
+            let mask = 0x80 >> (bit_idx % 8);
+            if i & (1 << self.pixel_bit(plane)) != 0 {
+                *byte |= mask;
+            } else {
+                *byte &= !mask;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::tile_codec::tests::{check_write_past_end, render_tiles};
+    use crate::tile_codec::*;
+
+    pub(crate) fn step(start: usize, step: usize, n: usize) -> Vec<usize> {
+        (0..n).map(|i| start + i * step).collect()
+    }
+
+    #[test]
+    fn matches_builtins() {
+        let data: Vec<u8> = (0..256u32).map(|i| (i * 0x9d + (i >> 3)) as u8).collect();
+
+        // MAME's usual NES-style 2bpp layout
+        let nes = GfxLayout::new(8, 8, vec![64, 0], step(0, 1, 8), step(0, 8, 8), 128).unwrap();
+        assert_eq!(
+            render_tiles(&nes, &data, 0, 2, 2),
+            render_tiles(&NESGraphics::new(), &data, 0, 2, 2)
+        );
+
+        // gfx_8x8x4_packed_msb
+        let packed =
+            GfxLayout::new(8, 8, vec![0, 1, 2, 3], step(0, 4, 8), step(0, 32, 8), 256).unwrap();
+        assert_eq!(
+            render_tiles(&packed, &data, 0, 2, 2),
+            render_tiles(&GenesisGraphics4bpp::new(), &data, 0, 2, 2)
+        );
+    }
+
+    #[test]
+    fn scrambled_roundtrip() {
+        // pixel bits 4 apart within 16-bit words, planes in separate halves of the data
+        let layout = GfxLayout::new(
+            4,
+            2,
+            vec![1024, 0, 3, 1027],
+            vec![0, 1, 2, 16],
+            vec![32, 64],
+            128,
+        )
+        .unwrap();
+        let mut data = vec![0u8; 192];
+        let mut expected = vec![0u8; 8 * 4];
+        for (i, c) in expected.iter_mut().enumerate() {
+            *c = (i * 5 % 16) as u8;
+            layout.write_px(&mut data, 0, 2, i % 8, i / 8, *c);
+        }
+        assert_eq!(render_tiles(&layout, &data, 0, 2, 2), expected);
+        check_write_past_end(&layout);
+
+        assert_eq!(
+            layout.tile_bit_ranges(1),
+            [
+                128 + 1024 + 32..128 + 1024 + 81,
+                160..209,
+                163..212,
+                128 + 1027 + 32..128 + 1027 + 81
+            ]
+        );
+    }
+
+    #[test]
+    fn validate() {
+        assert_eq!(
+            GfxLayout::new(1, 1, vec![], vec![0], vec![0], 1),
+            Err(GfxLayoutError::BadPlanes(0))
+        );
+        assert_eq!(
+            GfxLayout::new(2, 1, vec![0], vec![0], vec![0], 2),
+            Err(GfxLayoutError::WrongXOffsets {
+                expected: 2,
+                got: 1
+            })
+        );
+        assert_eq!(
+            GfxLayout::new(1, 1, vec![0], vec![0], vec![0], 0),
+            Err(GfxLayoutError::ZeroCharIncrement)
+        );
+        assert_eq!(
+            GfxLayout::new(1, 1, vec![usize::MAX - 4], vec![0], vec![0], 8),
+            Err(GfxLayoutError::OffsetTooLarge)
+        );
+        assert_eq!(
+            GfxLayout::new(1, 1, vec![0], vec![0], vec![0], MAX_OFFSET_BITS + 1),
+            Err(GfxLayoutError::OffsetTooLarge)
+        );
+
+        // the largest layout allowed never overflows, not even for far away tiles
+        let max = MAX_OFFSET_BITS;
+        let layout = GfxLayout::new(1, 1, vec![max], vec![max], vec![max], max).unwrap();
+        render_tiles(&layout, &[0xff], 7, 2, 2);
+        assert!(!layout.write_px(&mut [0xff], 7, 2, 1, 1, 1));
+        assert!(!layout.write_px(&mut [0xff], 7, 1, usize::MAX, 0, 1));
+        assert_eq!(
+            layout.tile_bit_ranges(usize::MAX),
+            [Range {
+                start: usize::MAX,
+                end: usize::MAX
+            }]
+        );
+    }
+
+    #[test]
+    fn render_past_end() {
+        // the first pixel's bits are out of range, the second's aren't
+        let layout = GfxLayout::new(2, 1, vec![0], vec![8, 0], vec![0], 1).unwrap();
+        let mut px = [5u8; 2];
+        layout.render(
+            &mut crate::InMemoryPixelWriter { w: 2, px: &mut px },
+            &[0x80],
+            0,
+            1,
+            1,
+        );
+        assert_eq!(px, [5, 1]);
+    }
+}
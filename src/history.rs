@@ -0,0 +1,300 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::Range;
+
+pub const DEFAULT_MAX_HISTORY_BYTES: usize = 16 * 1024 * 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct EditRun {
+    offset: usize,
+    old: Vec<u8>,
+    new: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Edit {
+    runs: Vec<EditRun>,
+}
+impl Edit {
+    fn mem_size(&self) -> usize {
+        self.runs.iter().map(|r| r.old.len() + r.new.len()).sum()
+    }
+
+    fn ranges(&self) -> Vec<Range<usize>> {
+        self.runs
+            .iter()
+            .map(|r| r.offset..(r.offset + r.old.len()))
+            .collect()
+    }
+}
+
+/// Records changes made to a byte buffer so that they can be undone and redone.
+///
+/// Callers announce which bytes they are about to modify with [`EditJournal::record`]
+/// and then close the edit with [`EditJournal::commit`].
+/// Everything recorded between two commits is undone as a single step.
+#[derive(Clone, Debug)]
+pub struct EditJournal {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    pending: BTreeMap<usize, u8>,
+    last_recorded: Range<usize>,
+    mem_used: usize,
+    max_mem: usize,
+}
+impl Default for EditJournal {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_HISTORY_BYTES)
+    }
+}
+impl EditJournal {
+    pub fn new(max_mem: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            pending: BTreeMap::new(),
+            last_recorded: 0..0,
+            mem_used: 0,
+            max_mem,
+        }
+    }
+
+    /// Saves the current contents of `data[range]` before they are modified
+    pub fn record(&mut self, data: &[u8], range: Range<usize>) {
+        let range = range.start..range.end.min(data.len());
+        // painting records the same tile over and over, and some tiles are huge
+        // (a Mode 7 map is all of VRAM), so skip ranges that were just saved
+        if range.start >= self.last_recorded.start && range.end <= self.last_recorded.end {
+            return;
+        }
+        for i in range.clone() {
+            self.pending.entry(i).or_insert(data[i]);
+        }
+        self.last_recorded = range;
+    }
+
+    /// Saves the current contents of the bytes covering bits `bit_range` of `data`
+    pub fn record_bits(&mut self, data: &[u8], bit_range: Range<usize>) {
+        self.record(data, (bit_range.start / 8)..bit_range.end.div_ceil(8));
+    }
+
+    /// Closes the pending edit, turning it into a single undo step.
+    /// Bytes that were recorded but ended up unchanged are dropped.
+    /// Returns whether anything was actually changed.
+    pub fn commit(&mut self, data: &[u8]) -> bool {
+        let pending = std::mem::take(&mut self.pending);
+        self.last_recorded = 0..0;
+
+        let mut edit = Edit::default();
+        for (offset, old) in pending {
+            let new = data[offset];
+            if old == new {
+                continue;
+            }
+            match edit.runs.last_mut() {
+                Some(run) if run.offset + run.old.len() == offset => {
+                    run.old.push(old);
+                    run.new.push(new);
+                }
+                _ => edit.runs.push(EditRun {
+                    offset,
+                    old: vec![old],
+                    new: vec![new],
+                }),
+            }
+        }
+
+        if edit.runs.is_empty() {
+            return false;
+        }
+
+        self.redo.clear();
+        self.mem_used += edit.mem_size();
+        self.undo.push_back(edit);
+        // always keep at least the most recent edit, even if it is huge
+        while self.mem_used > self.max_mem && self.undo.len() > 1 {
+            let evicted = self.undo.pop_front().unwrap();
+            self.mem_used -= evicted.mem_size();
+        }
+
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Reverts the most recent edit, returning the byte ranges that changed
+    pub fn undo(&mut self, data: &mut [u8]) -> Option<Vec<Range<usize>>> {
+        let edit = self.undo.pop_back()?;
+        for run in &edit.runs {
+            data[run.offset..(run.offset + run.old.len())].copy_from_slice(&run.old);
+        }
+        let ranges = edit.ranges();
+        self.mem_used -= edit.mem_size();
+        self.redo.push(edit);
+        Some(ranges)
+    }
+
+    /// Reapplies the most recently undone edit, returning the byte ranges that changed
+    pub fn redo(&mut self, data: &mut [u8]) -> Option<Vec<Range<usize>>> {
+        let edit = self.redo.pop()?;
+        for run in &edit.runs {
+            data[run.offset..(run.offset + run.new.len())].copy_from_slice(&run.new);
+        }
+        let ranges = edit.ranges();
+        self.mem_used += edit.mem_size();
+        self.undo.push_back(edit);
+        Some(ranges)
+    }
+}
+
+/// Finds the byte ranges where `new` differs from `old`.
+/// Bytes past the end of the shorter buffer always count as different.
+pub fn diff_ranges(old: &[u8], new: &[u8]) -> Vec<Range<usize>> {
+    let mut ret: Vec<Range<usize>> = Vec::new();
+    let common_len = old.len().min(new.len());
+
+    for i in 0..common_len {
+        if old[i] != new[i] {
+            match ret.last_mut() {
+                Some(r) if r.end == i => r.end = i + 1,
+                _ => ret.push(i..(i + 1)),
+            }
+        }
+    }
+
+    let total_len = old.len().max(new.len());
+    if total_len > common_len {
+        match ret.last_mut() {
+            Some(r) if r.end == common_len => r.end = total_len,
+            _ => ret.push(common_len..total_len),
+        }
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_redo() {
+        let mut data = vec![0u8; 8];
+        let mut j = EditJournal::default();
+
+        j.record(&data, 1..3);
+        data[1] = 1;
+        data[2] = 2;
+        assert!(j.commit(&data));
+
+        j.record(&data, 2..3);
+        data[2] = 3;
+        assert!(j.commit(&data));
+
+        assert_eq!(j.undo(&mut data), Some(vec![Range { start: 2, end: 3 }]));
+        assert_eq!(data, [0, 1, 2, 0, 0, 0, 0, 0]);
+        assert_eq!(j.undo(&mut data), Some(vec![Range { start: 1, end: 3 }]));
+        assert_eq!(data, [0; 8]);
+        assert_eq!(j.undo(&mut data), None);
+
+        assert_eq!(j.redo(&mut data), Some(vec![Range { start: 1, end: 3 }]));
+        assert_eq!(data, [0, 1, 2, 0, 0, 0, 0, 0]);
+        assert!(j.can_redo());
+
+        // a new edit discards the redo stack
+        j.record(&data, 7..8);
+        data[7] = 7;
+        assert!(j.commit(&data));
+        assert!(!j.can_redo());
+        assert_eq!(j.redo(&mut data), None);
+    }
+
+    #[test]
+    fn unchanged_bytes_dropped() {
+        let mut data = vec![0u8; 8];
+        let mut j = EditJournal::default();
+
+        j.record(&data, 0..8);
+        assert!(!j.commit(&data));
+        assert!(!j.can_undo());
+
+        j.record(&data, 0..8);
+        data[1] = 1;
+        data[5] = 5;
+        assert!(j.commit(&data));
+        assert_eq!(j.undo(&mut data), Some(vec![1..2, 5..6]));
+    }
+
+    #[test]
+    fn record_keeps_first_value() {
+        let mut data = vec![0u8; 4];
+        let mut j = EditJournal::default();
+
+        j.record_bits(&data, 3..12);
+        data[0] = 1;
+        data[1] = 1;
+        j.record(&data, 0..2);
+        data[0] = 2;
+        assert!(j.commit(&data));
+        j.undo(&mut data);
+        assert_eq!(data, [0; 4]);
+    }
+
+    #[test]
+    fn repeated_ranges() {
+        let mut data = vec![0u8; 4];
+        let mut j = EditJournal::default();
+
+        j.record(&data, 0..4);
+        data[1] = 1;
+        // already saved, must not overwrite the original value
+        j.record(&data, 1..3);
+        data[2] = 2;
+        assert!(j.commit(&data));
+
+        // a new edit has to save the same range again
+        j.record(&data, 1..3);
+        data[1] = 3;
+        assert!(j.commit(&data));
+        assert_eq!(j.undo(&mut data), Some(vec![Range { start: 1, end: 2 }]));
+        assert_eq!(data, [0, 1, 2, 0]);
+        assert_eq!(j.undo(&mut data), Some(vec![Range { start: 1, end: 3 }]));
+        assert_eq!(data, [0; 4]);
+    }
+
+    #[test]
+    fn bounded_memory() {
+        let mut data = vec![0u8; 8];
+        let mut j = EditJournal::new(4);
+
+        for i in 0..4 {
+            j.record(&data, i..(i + 1));
+            data[i] = 1;
+            assert!(j.commit(&data));
+        }
+
+        assert!(j.undo(&mut data).is_some());
+        assert!(j.undo(&mut data).is_some());
+        assert!(j.undo(&mut data).is_none());
+        assert_eq!(data, [1, 1, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn diff() {
+        assert_eq!(diff_ranges(&[1, 2, 3], &[1, 2, 3]), []);
+        assert_eq!(
+            diff_ranges(&[1, 2, 3, 4, 5], &[0, 2, 0, 0, 5]),
+            [0..1, 2..4]
+        );
+        assert_eq!(
+            diff_ranges(&[1, 2], &[1, 3, 4]),
+            [Range { start: 1, end: 3 }]
+        );
+        assert_eq!(diff_ranges(&[1, 2, 3], &[0, 2]), [0..1, 2..3]);
+    }
+}
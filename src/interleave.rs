@@ -0,0 +1,67 @@
+/// Combines several files byte by byte, the way a board with multiple ROM chips
+/// on a wider data bus would see them.
+/// Shorter files are padded with zeros.
+pub fn interleave_bytes(files: &[&[u8]]) -> Vec<u8> {
+    let max_len = files.iter().map(|f| f.len()).max().unwrap_or(0);
+    let mut ret = Vec::with_capacity(max_len * files.len());
+    for i in 0..max_len {
+        for f in files {
+            ret.push(f.get(i).copied().unwrap_or(0));
+        }
+    }
+    ret
+}
+
+/// Extracts file `idx` out of `num_files` byte-interleaved files, undoing [`interleave_bytes`]
+pub fn deinterleave_bytes(data: &[u8], num_files: usize, idx: usize, len: usize) -> Vec<u8> {
+    data.iter()
+        .skip(idx)
+        .step_by(num_files)
+        .take(len)
+        .copied()
+        .collect()
+}
+
+/// Swaps file `idx` of the byte-interleaved files in `data` for `new_file`,
+/// which may have a different length
+pub fn replace_file(data: &[u8], lens: &[usize], idx: usize, new_file: &[u8]) -> Vec<u8> {
+    let files: Vec<Vec<u8>> = lens
+        .iter()
+        .enumerate()
+        .map(|(i, &len)| deinterleave_bytes(data, lens.len(), i, len))
+        .collect();
+    let files: Vec<&[u8]> = files
+        .iter()
+        .enumerate()
+        .map(|(i, f)| if i == idx { new_file } else { f })
+        .collect();
+    interleave_bytes(&files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let a = [1, 2, 3];
+        let b = [4, 5];
+        let combined = interleave_bytes(&[&a, &b]);
+        assert_eq!(combined, [1, 4, 2, 5, 3, 0]);
+        assert_eq!(deinterleave_bytes(&combined, 2, 0, a.len()), a);
+        assert_eq!(deinterleave_bytes(&combined, 2, 1, b.len()), b);
+    }
+
+    #[test]
+    fn replace() {
+        let combined = interleave_bytes(&[&[1, 2, 3], &[4, 5]]);
+        assert_eq!(
+            replace_file(&combined, &[3, 2], 1, &[6]),
+            [1, 6, 2, 0, 3, 0]
+        );
+        assert_eq!(
+            replace_file(&combined, &[3, 2], 1, &[6, 7, 8, 9]),
+            [1, 6, 2, 7, 3, 8, 0, 9]
+        );
+    }
+}
@@ -1,13 +1,28 @@
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
 
-use wasm_bindgen::prelude::*;
-use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, HtmlElement};
+use wasm_bindgen::{prelude::*, Clamped};
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, HtmlElement, ImageData};
 
+pub mod arrangement;
+pub mod codec_descriptor;
+pub mod codec_file;
+pub mod gfx_layout;
+pub mod history;
+pub mod interleave;
 pub mod linear_codec;
+pub mod mame_layout;
 pub mod tile_codec;
 
 pub mod palette;
+pub mod patch;
+pub mod png_import;
 
+use crate::arrangement::{TileArrangement, TileOrder};
+use crate::codec_descriptor::CodecDescriptor;
+use crate::gfx_layout::GfxLayout;
+use crate::history::{diff_ranges, EditJournal};
 use crate::linear_codec::*;
 use crate::tile_codec::*;
 
@@ -36,19 +51,21 @@ pub trait AbstractPixelTarget {
     fn draw_px_pal(&mut self, x: usize, y: usize, i: u8);
 }
 
-struct CanvasPixelWriter<'a> {
-    app: &'a TileCorruptorAppInst,
+struct RgbaPixelWriter<'a> {
+    w: usize,
+    px: &'a mut [u8],
+    pal: &'a [(u8, u8, u8)],
 }
-impl<'a> AbstractPixelTarget for CanvasPixelWriter<'a> {
+impl<'a> AbstractPixelTarget for RgbaPixelWriter<'a> {
     fn draw_px_rgb(&mut self, x: usize, y: usize, r: u8, g: u8, b: u8) {
-        self.app
-            .ctx
-            .set_fill_style(&format!("rgb({},{},{})", r, g, b).into());
-        self.app.ctx.fill_rect(x as f64, y as f64, 1.0, 1.0);
+        self.px[(y * self.w + x) * 4] = r;
+        self.px[(y * self.w + x) * 4 + 1] = g;
+        self.px[(y * self.w + x) * 4 + 2] = b;
+        self.px[(y * self.w + x) * 4 + 3] = 255;
     }
 
     fn draw_px_pal(&mut self, x: usize, y: usize, i: u8) {
-        let color = self.app.pal[i as usize];
+        let color = self.pal[i as usize];
         self.draw_px_rgb(x, y, color.0, color.1, color.2);
     }
 }
@@ -58,6 +75,7 @@ struct InMemoryPixelWriter<'a> {
     px: &'a mut [u8],
 }
 impl<'a> AbstractPixelTarget for InMemoryPixelWriter<'a> {
+    #[allow(clippy::identity_op)]
     fn draw_px_rgb(&mut self, x: usize, y: usize, r: u8, g: u8, b: u8) {
         self.px[(y * self.w + x) * 3 + 0] = r;
         self.px[(y * self.w + x) * 3 + 1] = g;
@@ -111,6 +129,98 @@ pub const BUILTIN_GRAPHICS_CODECS: &[BuiltinGraphicsCodec] = &[
         tile_codec: Some(&TileGraphics8bpp::new()),
         lin_codec: None,
     },
+    BuiltinGraphicsCodec {
+        i18n_name: "sms-4bpp",
+        is_tiled: true,
+        tile_codec: Some(&SMSGraphics4bpp::new()),
+        lin_codec: None,
+    },
+    BuiltinGraphicsCodec {
+        i18n_name: "snes-2bpp",
+        is_tiled: true,
+        tile_codec: Some(&SNESGraphics2bpp::new()),
+        lin_codec: None,
+    },
+    BuiltinGraphicsCodec {
+        i18n_name: "snes-3bpp",
+        is_tiled: true,
+        tile_codec: Some(&SNESGraphics3bpp::new()),
+        lin_codec: None,
+    },
+    BuiltinGraphicsCodec {
+        i18n_name: "snes-4bpp",
+        is_tiled: true,
+        tile_codec: Some(&SNESGraphics4bpp::new()),
+        lin_codec: None,
+    },
+    BuiltinGraphicsCodec {
+        i18n_name: "snes-8bpp",
+        is_tiled: true,
+        tile_codec: Some(&SNESGraphics8bpp::new()),
+        lin_codec: None,
+    },
+    BuiltinGraphicsCodec {
+        i18n_name: "snes-mode7-tiles",
+        is_tiled: true,
+        tile_codec: Some(&SNESMode7TileGraphics::new()),
+        lin_codec: None,
+    },
+    BuiltinGraphicsCodec {
+        i18n_name: "snes-mode7-map",
+        is_tiled: true,
+        tile_codec: Some(&SNESMode7MapGraphics::new()),
+        lin_codec: None,
+    },
+    BuiltinGraphicsCodec {
+        i18n_name: "pce-4bpp",
+        is_tiled: true,
+        tile_codec: Some(&PCEGraphics4bpp::new()),
+        lin_codec: None,
+    },
+    BuiltinGraphicsCodec {
+        i18n_name: "pce-sprite",
+        is_tiled: true,
+        tile_codec: Some(&PCESpriteGraphics::wrap(
+            PlanarNonInterleavedTileGraphics::new(),
+        )),
+        lin_codec: None,
+    },
+    BuiltinGraphicsCodec {
+        i18n_name: "neogeo-sprite",
+        is_tiled: true,
+        tile_codec: Some(&NeoGeoSpriteGraphics::new()),
+        lin_codec: None,
+    },
+    BuiltinGraphicsCodec {
+        i18n_name: "vb-2bpp",
+        is_tiled: true,
+        tile_codec: Some(&VirtualBoyGraphics::new()),
+        lin_codec: None,
+    },
+    BuiltinGraphicsCodec {
+        i18n_name: "ngp-2bpp",
+        is_tiled: true,
+        tile_codec: Some(&NGPGraphics::wrap(NonPlanarTileGraphics::new())),
+        lin_codec: None,
+    },
+    BuiltinGraphicsCodec {
+        i18n_name: "ws-2bpp",
+        is_tiled: true,
+        tile_codec: Some(&WonderSwanGraphics2bpp::new()),
+        lin_codec: None,
+    },
+    BuiltinGraphicsCodec {
+        i18n_name: "ws-4bpp",
+        is_tiled: true,
+        tile_codec: Some(&WonderSwanGraphics4bpp::new()),
+        lin_codec: None,
+    },
+    BuiltinGraphicsCodec {
+        i18n_name: "ws-4bpp-packed",
+        is_tiled: true,
+        tile_codec: Some(&WonderSwanPackedGraphics4bpp::new()),
+        lin_codec: None,
+    },
     BuiltinGraphicsCodec {
         i18n_name: "lin-1bpp-msbfirst",
         is_tiled: false,
@@ -130,6 +240,64 @@ pub fn wasm_get_builtin_graphics_codecs() -> Vec<BuiltinGraphicsCodec> {
     BUILTIN_GRAPHICS_CODECS.to_vec()
 }
 
+thread_local! {
+    // codecs loaded from definition files, numbered after BUILTIN_GRAPHICS_CODECS
+    static LOADED_GRAPHICS_CODECS: RefCell<Vec<(&'static str, &'static CodecDescriptor)>> =
+        const { RefCell::new(Vec::new()) };
+    // every descriptor ever loaded, so that loading a file again doesn't leak it again
+    static LOADED_DESCRIPTORS: RefCell<Vec<&'static CodecDescriptor>> =
+        const { RefCell::new(Vec::new()) };
+}
+
+/// The builtin codecs followed by every codec loaded with `wasm_load_codecs`
+#[wasm_bindgen]
+pub fn wasm_get_graphics_codecs() -> Vec<BuiltinGraphicsCodec> {
+    let mut ret = BUILTIN_GRAPHICS_CODECS.to_vec();
+    LOADED_GRAPHICS_CODECS.with_borrow(|loaded| {
+        ret.extend(loaded.iter().map(|&(name, desc)| BuiltinGraphicsCodec {
+            i18n_name: name,
+            is_tiled: desc.tiled,
+            tile_codec: Some(desc),
+            lin_codec: Some(desc),
+        }))
+    });
+    ret
+}
+
+/// Adds the codecs in a definition file to the end of `wasm_get_graphics_codecs`,
+/// replacing loaded codecs with the same name. Returns the number of codecs in the file.
+#[wasm_bindgen]
+pub fn wasm_load_codecs(text: &str) -> Result<usize, JsError> {
+    let codecs = codec_file::parse_codecs(text)?;
+    let num_codecs = codecs.len();
+    LOADED_GRAPHICS_CODECS.with_borrow_mut(|loaded| {
+        for (name, desc) in codecs {
+            // codecs are referred to by index, so they have to live forever
+            let desc = LOADED_DESCRIPTORS.with_borrow_mut(|descs| {
+                match descs.iter().find(|&&d| *d == desc) {
+                    Some(&d) => d,
+                    None => {
+                        let d: &'static CodecDescriptor = Box::leak(Box::new(desc));
+                        descs.push(d);
+                        d
+                    }
+                }
+            });
+            match loaded.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, d)) => *d = desc,
+                None => loaded.push((Box::leak(name.into_boxed_str()), desc)),
+            }
+        }
+    });
+    Ok(num_codecs)
+}
+
+/// Serializes a codec so that it can be loaded again with `wasm_load_codecs`
+#[wasm_bindgen]
+pub fn wasm_format_codec(name: &str, desc: &CodecDescriptor) -> String {
+    codec_file::format_codec(name, desc)
+}
+
 enum TileCorruptorTiledOrLinear {
     Tiled {
         tiles_width: usize,
@@ -145,6 +313,9 @@ enum TileCorruptorTiledOrLinear {
 
 #[wasm_bindgen]
 pub struct TileCorruptorAppInst {
+    orig_data: Vec<u8>,
+    // contents as of the last save, for the dirty flag
+    saved_data: Vec<u8>,
     data: Vec<u8>,
     data_bit_off: usize,
     pal: Cow<'static, [(u8, u8, u8)]>,
@@ -152,12 +323,24 @@ pub struct TileCorruptorAppInst {
     ctx: CanvasRenderingContext2d,
     px_scale: f64,
     tiled_or_linear: TileCorruptorTiledOrLinear,
+    arrangement: TileArrangement,
+    history: EditJournal,
+    edit_group_open: bool,
+    // lengths of the files that were byte-interleaved into `data`, if any,
+    // and of the same files in `orig_data` (patches can resize them)
+    interleaved_lens: Vec<usize>,
+    orig_interleaved_lens: Vec<usize>,
+    // codecs only need to be leaked once, no matter how often they are selected
+    custom_codecs: Vec<&'static CodecDescriptor>,
+    custom_layouts: Vec<&'static GfxLayout>,
 }
 #[wasm_bindgen]
 impl TileCorruptorAppInst {
     #[wasm_bindgen(constructor)]
     pub fn new(data: &[u8]) -> Self {
         Self {
+            orig_data: data.to_owned(),
+            saved_data: data.to_owned(),
             data: data.to_owned(),
             data_bit_off: 0,
             pal: (&palette::DEFAULT_PAL).into(),
@@ -169,40 +352,121 @@ impl TileCorruptorAppInst {
                 tiles_height: 32,
                 tile_codec: BUILTIN_GRAPHICS_CODECS[0].tile_codec.unwrap(),
             },
+            arrangement: TileArrangement::default(),
+            history: EditJournal::default(),
+            edit_group_open: false,
+            interleaved_lens: Vec::new(),
+            orig_interleaved_lens: Vec::new(),
+            custom_codecs: Vec::new(),
+            custom_layouts: Vec::new(),
         }
     }
 
+    /// Opens two ROM chips that the hardware reads in parallel (e.g. Neo Geo C ROM pairs).
+    /// Bytes of `first` end up at even offsets and bytes of `second` at odd offsets.
+    pub fn new_interleaved(first: &[u8], second: &[u8]) -> Self {
+        let mut ret = Self::new(&interleave::interleave_bytes(&[first, second]));
+        ret.interleaved_lens = vec![first.len(), second.len()];
+        ret.orig_interleaved_lens.clone_from(&ret.interleaved_lens);
+        ret
+    }
+
+    pub fn num_interleaved_files(&self) -> usize {
+        self.interleaved_lens.len()
+    }
+
+    /// Splits the edited data back into one of the files passed to `new_interleaved`
+    pub fn export_deinterleaved(&self, idx: usize) -> Option<Vec<u8>> {
+        let len = *self.interleaved_lens.get(idx)?;
+        Some(interleave::deinterleave_bytes(
+            &self.data,
+            self.interleaved_lens.len(),
+            idx,
+            len,
+        ))
+    }
+
     pub fn change_codec(&mut self, new_codec_idx: usize) {
-        let codec = BUILTIN_GRAPHICS_CODECS[new_codec_idx];
-        if codec.is_tiled {
-            let (tiles_width, tiles_height) = match self.tiled_or_linear {
-                TileCorruptorTiledOrLinear::Tiled {
-                    tiles_width,
-                    tiles_height,
-                    ..
-                } => (tiles_width, tiles_height),
-                _ => (32, 32),
-            };
-            self.tiled_or_linear = TileCorruptorTiledOrLinear::Tiled {
-                tiles_width,
-                tiles_height,
-                tile_codec: codec.tile_codec.unwrap(),
-            };
-            self.resize();
-            self.render();
-        } else {
-            let (width, height) = match self.tiled_or_linear {
-                TileCorruptorTiledOrLinear::Linear { width, height, .. } => (width, height),
-                _ => (256, 256),
-            };
-            self.tiled_or_linear = TileCorruptorTiledOrLinear::Linear {
-                width,
-                height,
-                lin_codec: codec.lin_codec.unwrap(),
-            };
-            self.resize();
-            self.render();
-        }
+        self.switch_codec(wasm_get_graphics_codecs()[new_codec_idx]);
+    }
+
+    /// Switches to a codec built at runtime, e.g. from the custom codec dialog
+    pub fn set_custom_codec(&mut self, desc: &CodecDescriptor) -> Result<(), JsError> {
+        desc.validate()?;
+        let desc = match self.custom_codecs.iter().find(|&&d| d == desc) {
+            Some(&d) => d,
+            None => {
+                let d: &'static CodecDescriptor = Box::leak(Box::new(*desc));
+                self.custom_codecs.push(d);
+                d
+            }
+        };
+        self.switch_codec(BuiltinGraphicsCodec {
+            i18n_name: "custom",
+            is_tiled: desc.tiled,
+            tile_codec: Some(desc),
+            lin_codec: Some(desc),
+        });
+        self.update_status_bar();
+        Ok(())
+    }
+
+    /// Switches to a codec that reads every pixel bit from an explicit offset, like MAME's
+    /// `gfx_layout`. All offsets are in bits, see [`GfxLayout`].
+    pub fn set_gfx_layout(
+        &mut self,
+        width: usize,
+        height: usize,
+        plane_offsets: &[u32],
+        x_offsets: &[u32],
+        y_offsets: &[u32],
+        char_increment: usize,
+    ) -> Result<(), JsError> {
+        let to_usize = |offsets: &[u32]| offsets.iter().map(|&o| o as usize).collect();
+        let layout = GfxLayout::new(
+            width,
+            height,
+            to_usize(plane_offsets),
+            to_usize(x_offsets),
+            to_usize(y_offsets),
+            char_increment,
+        )?;
+        self.switch_gfx_layout(layout);
+        Ok(())
+    }
+
+    /// Switches to a `gfx_layout` struct copied from a MAME driver.
+    /// `RGN_FRAC` offsets are taken as fractions of the whole file, so the layout has to be
+    /// set again after opening a different file.
+    pub fn set_mame_layout(&mut self, text: &str) -> Result<(), JsError> {
+        let layout = mame_layout::parse_mame_layout(text, self.data.len())?;
+        self.switch_gfx_layout(layout);
+        Ok(())
+    }
+
+    /// Groups tiles into sprites `block_w` x `block_h` tiles big, stored consecutively.
+    /// The `*_column_major` flags choose the order within a sprite and across the view.
+    pub fn set_arrangement(
+        &mut self,
+        block_w: usize,
+        block_h: usize,
+        tiles_column_major: bool,
+        blocks_column_major: bool,
+    ) {
+        let order = |column_major| {
+            if column_major {
+                TileOrder::ColumnMajor
+            } else {
+                TileOrder::RowMajor
+            }
+        };
+        self.arrangement = TileArrangement::new(
+            block_w.max(1),
+            block_h.max(1),
+            order(tiles_column_major),
+            order(blocks_column_major),
+        );
+        self.render();
     }
 
     pub fn resize(&self) {
@@ -262,6 +526,7 @@ impl TileCorruptorAppInst {
             .dyn_into::<HtmlElement>()
             .unwrap();
 
+        #[allow(clippy::manual_is_multiple_of)]
         let file_offs = if self.data_bit_off % 8 == 0 {
             format!("0x{:08X}", self.data_bit_off / 8)
         } else {
@@ -302,43 +567,14 @@ impl TileCorruptorAppInst {
     }
 
     pub fn render(&self) {
-        match self.tiled_or_linear {
-            TileCorruptorTiledOrLinear::Tiled {
-                tiles_width,
-                tiles_height,
-                tile_codec,
-            } => {
-                self.ctx.clear_rect(
-                    0.0,
-                    0.0,
-                    (tiles_width * tile_codec.tile_width()) as f64,
-                    (tiles_height * tile_codec.tile_height()) as f64,
-                );
-
-                tile_codec.render(
-                    &mut CanvasPixelWriter { app: self },
-                    &self.data[(self.data_bit_off / 8)..],
-                    (self.data_bit_off % 8) as u8,
-                    tiles_width,
-                    tiles_height,
-                );
-            }
-            TileCorruptorTiledOrLinear::Linear {
-                width,
-                height,
-                lin_codec,
-            } => {
-                self.ctx.clear_rect(0.0, 0.0, width as f64, height as f64);
-
-                lin_codec.render(
-                    &mut CanvasPixelWriter { app: self },
-                    &self.data[(self.data_bit_off / 8)..],
-                    (self.data_bit_off % 8) as u8,
-                    width,
-                    height,
-                );
-            }
-        }
+        let (w, h) = self.view_dims();
+        let mut px = vec![0u8; w * h * 4];
+        self.render_to(&mut RgbaPixelWriter {
+            w,
+            px: &mut px,
+            pal: &self.pal,
+        });
+        self.blit(&px, 0, 0, w, h);
     }
 
     pub fn export_png(&self) -> Vec<u8> {
@@ -372,13 +608,7 @@ impl TileCorruptorAppInst {
                     pixels = vec![0u8; w * h * 3];
                 }
 
-                tile_codec.render(
-                    &mut InMemoryPixelWriter { w, px: &mut pixels },
-                    &self.data[(self.data_bit_off / 8)..],
-                    (self.data_bit_off % 8) as u8,
-                    tiles_width,
-                    tiles_height,
-                );
+                self.render_to(&mut InMemoryPixelWriter { w, px: &mut pixels });
 
                 let mut png_writer = png_encoder.write_header().unwrap();
                 png_writer.write_image_data(&pixels).unwrap();
@@ -432,6 +662,237 @@ impl TileCorruptorAppInst {
         }
     }
 
+    pub fn paint_at(&mut self, canvas_x: f64, canvas_y: f64, color: u8) {
+        if let Some((x, y)) = self.canvas_to_px(canvas_x, canvas_y) {
+            if self.write_px(x, y, color) {
+                self.finish_edit();
+                self.rerender_pxs(&[(x, y)]);
+            }
+        }
+    }
+
+    pub fn draw_line(&mut self, x0: f64, y0: f64, x1: f64, y1: f64, color: u8) {
+        let (w, h) = self.view_dims();
+        let to_px = |x: f64, y: f64| {
+            (
+                (x / self.px_scale).floor() as isize,
+                (y / self.px_scale).floor() as isize,
+            )
+        };
+        let (mut x, mut y) = to_px(x0, y0);
+        let (x1, y1) = to_px(x1, y1);
+
+        // Bresenham, clipped to the view
+        let dx = (x1 - x).abs();
+        let dy = -(y1 - y).abs();
+        let sx = if x < x1 { 1 } else { -1 };
+        let sy = if y < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+        let mut changed = Vec::new();
+        loop {
+            if x >= 0
+                && y >= 0
+                && (x as usize) < w
+                && (y as usize) < h
+                && self.write_px(x as usize, y as usize, color)
+            {
+                changed.push((x as usize, y as usize));
+            }
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+        self.finish_edit();
+        self.rerender_pxs(&changed);
+    }
+
+    pub fn flood_fill(&mut self, canvas_x: f64, canvas_y: f64, color: u8) {
+        let (x, y) = if let Some(xy) = self.canvas_to_px(canvas_x, canvas_y) {
+            xy
+        } else {
+            return;
+        };
+        if color as usize >= self.num_palette_colors() {
+            return;
+        }
+
+        let (w, h) = self.view_dims();
+        let mut pixels = vec![0u8; w * h];
+        self.render_to(&mut InMemoryPixelWriter { w, px: &mut pixels });
+
+        let target = pixels[y * w + x];
+        if target == color {
+            return;
+        }
+
+        let mut changed = Vec::new();
+        let mut stack = vec![(x, y)];
+        while let Some((x, y)) = stack.pop() {
+            if pixels[y * w + x] != target {
+                continue;
+            }
+            pixels[y * w + x] = color;
+            if self.write_px(x, y, color) {
+                changed.push((x, y));
+            }
+
+            if x > 0 {
+                stack.push((x - 1, y));
+            }
+            if x + 1 < w {
+                stack.push((x + 1, y));
+            }
+            if y > 0 {
+                stack.push((x, y - 1));
+            }
+            if y + 1 < h {
+                stack.push((x, y + 1));
+            }
+        }
+        self.finish_edit();
+        self.rerender_pxs(&changed);
+    }
+
+    pub fn begin_edit(&mut self) {
+        self.edit_group_open = true;
+    }
+    pub fn end_edit(&mut self) {
+        self.edit_group_open = false;
+        self.finish_edit();
+    }
+
+    pub fn undo(&mut self) -> bool {
+        self.end_edit();
+        if self.history.undo(&mut self.data).is_some() {
+            self.render();
+            true
+        } else {
+            false
+        }
+    }
+    pub fn redo(&mut self) -> bool {
+        self.end_edit();
+        if self.history.redo(&mut self.data).is_some() {
+            self.render();
+            true
+        } else {
+            false
+        }
+    }
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    /// Returns the number of pixels that had to be mapped to a different color
+    pub fn import_png(&mut self, bytes: &[u8]) -> Result<usize, JsError> {
+        let num_colors = self.num_palette_colors().min(self.pal.len());
+        let img = png_import::decode_png(bytes, &self.pal[..num_colors])?;
+        let (w, h) = self.view_dims();
+        img.check_fits(w, h, self.num_palette_colors())?;
+
+        self.end_edit();
+        for y in 0..h {
+            for x in 0..w {
+                self.write_px(x, y, img.px[y * w + x]);
+            }
+        }
+        self.finish_edit();
+        self.render();
+
+        Ok(img.lossy_px)
+    }
+
+    pub fn export_data(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+
+    /// Patches are made per file, `idx` picks one of the interleaved files (or 0 otherwise)
+    pub fn export_ips(&self, idx: usize) -> Result<Vec<u8>, JsError> {
+        let (orig, new) = self.file_versions(idx)?;
+        Ok(patch::ips::create_ips(&orig, &new)?)
+    }
+
+    pub fn export_bps(&self, idx: usize) -> Result<Vec<u8>, JsError> {
+        let (orig, new) = self.file_versions(idx)?;
+        Ok(patch::bps::create_bps(&orig, &new))
+    }
+
+    pub fn export_ups(&self, idx: usize) -> Result<Vec<u8>, JsError> {
+        let (orig, new) = self.file_versions(idx)?;
+        Ok(patch::ups::create_ups(&orig, &new))
+    }
+
+    /// Applies an IPS, BPS or UPS patch to file `idx`.
+    /// Interleaved files are patched one at a time, like `export_*` creates their patches.
+    /// Returns `false` if the patch changed the file size, which clears the undo history.
+    pub fn apply_patch(&mut self, idx: usize, patch: &[u8]) -> Result<bool, JsError> {
+        let mut lens = self.interleaved_lens.clone();
+        let data = if lens.is_empty() {
+            if idx != 0 {
+                return Err(JsError::new("only one file is open"));
+            }
+            patch::apply_patch(&self.data, patch)?
+        } else {
+            let (_, file) = self.file_versions(idx)?;
+            let file = patch::apply_patch(&file, patch)?;
+            lens[idx] = file.len();
+            interleave::replace_file(&self.data, &self.interleaved_lens, idx, &file)
+        };
+
+        self.end_edit();
+        // the journal can't undo a change in size
+        let resized = data.len() != self.data.len() || lens != self.interleaved_lens;
+        if resized {
+            self.history = EditJournal::default();
+        } else {
+            self.history.record(&self.data, 0..self.data.len());
+        }
+        self.data = data;
+        self.interleaved_lens = lens;
+        if !resized {
+            self.history.commit(&self.data);
+        }
+
+        if self.data_bit_off >= self.data.len() * 8 {
+            self.data_bit_off = 0;
+        }
+        self.render();
+        self.update_status_bar();
+
+        Ok(!resized)
+    }
+
+    /// Called by JS once the modified file has been saved
+    pub fn mark_saved(&mut self) {
+        self.saved_data.clone_from(&self.data);
+    }
+
+    /// Whether there are changes since the file was opened or last saved
+    pub fn is_dirty(&self) -> bool {
+        self.data != self.saved_data
+    }
+
+    /// Byte ranges that differ from the file as originally loaded,
+    /// flattened as `[start0, end0, start1, end1, ...]` for JS
+    pub fn modified_ranges(&self) -> Vec<usize> {
+        diff_ranges(&self.orig_data, &self.data)
+            .into_iter()
+            .flat_map(|r| [r.start, r.end])
+            .collect()
+    }
+
     pub fn width_minus(&mut self) {
         match self.tiled_or_linear {
             TileCorruptorTiledOrLinear::Tiled {
@@ -555,11 +1016,12 @@ impl TileCorruptorAppInst {
         match self.tiled_or_linear {
             TileCorruptorTiledOrLinear::Tiled {
                 tiles_width,
+                tiles_height,
                 tile_codec,
-                ..
             } => {
-                let bits_per_row =
-                    tile_codec.bits_per_tile() * tiles_width * if faster { 8 } else { 1 };
+                let bits_per_row = tile_codec.bits_per_tile()
+                    * self.arrangement.tiles_per_line(tiles_width, tiles_height)
+                    * if faster { 8 } else { 1 };
                 if self.data_bit_off >= bits_per_row {
                     self.data_bit_off -= bits_per_row;
                 } else {
@@ -584,11 +1046,13 @@ impl TileCorruptorAppInst {
         match self.tiled_or_linear {
             TileCorruptorTiledOrLinear::Tiled {
                 tiles_width,
+                tiles_height,
                 tile_codec,
-                ..
             } => {
                 let new_off = self.data_bit_off
-                    + tile_codec.bits_per_tile() * tiles_width * if faster { 8 } else { 1 };
+                    + tile_codec.bits_per_tile()
+                        * self.arrangement.tiles_per_line(tiles_width, tiles_height)
+                        * if faster { 8 } else { 1 };
                 if new_off < self.data.len() * 8 {
                     self.data_bit_off = new_off;
                 }
@@ -670,8 +1134,350 @@ impl TileCorruptorAppInst {
     }
 }
 
+/// How many tiles of `codec` cover roughly `px_width` x `px_height` pixels.
+/// Switching codecs keeps the view's size in pixels rather than in tiles,
+/// otherwise going from 8x8 tiles to e.g. a 1024x1024 Mode 7 map would need a gigantic canvas.
+fn tiles_for_view(px_width: usize, px_height: usize, codec: &dyn TileCodec) -> (usize, usize) {
+    (
+        (px_width / codec.tile_width()).max(1),
+        (px_height / codec.tile_height()).max(1),
+    )
+}
+
+impl TileCorruptorAppInst {
+    /// Original and edited contents of file `idx`, see `export_ips`
+    fn file_versions(&self, idx: usize) -> Result<(Vec<u8>, Vec<u8>), JsError> {
+        if self.interleaved_lens.is_empty() {
+            if idx != 0 {
+                return Err(JsError::new("only one file is open"));
+            }
+            return Ok((self.orig_data.clone(), self.data.clone()));
+        }
+
+        if idx >= self.interleaved_lens.len() {
+            return Err(JsError::new("no such interleaved file"));
+        }
+        let split =
+            |data, lens: &[usize]| interleave::deinterleave_bytes(data, lens.len(), idx, lens[idx]);
+        Ok((
+            split(&self.orig_data, &self.orig_interleaved_lens),
+            split(&self.data, &self.interleaved_lens),
+        ))
+    }
+
+    fn switch_gfx_layout(&mut self, layout: GfxLayout) {
+        let layout = match self.custom_layouts.iter().find(|&&l| *l == layout) {
+            Some(&l) => l,
+            None => {
+                let l: &'static GfxLayout = Box::leak(Box::new(layout));
+                self.custom_layouts.push(l);
+                l
+            }
+        };
+        self.switch_codec(BuiltinGraphicsCodec {
+            i18n_name: "gfx-layout",
+            is_tiled: true,
+            tile_codec: Some(layout),
+            lin_codec: None,
+        });
+        self.update_status_bar();
+    }
+
+    fn switch_codec(&mut self, codec: BuiltinGraphicsCodec) {
+        if codec.is_tiled {
+            let (px_width, px_height) = match self.tiled_or_linear {
+                TileCorruptorTiledOrLinear::Tiled { .. } => self.view_dims(),
+                _ => (256, 256),
+            };
+            let tile_codec = codec.tile_codec.unwrap();
+            let (tiles_width, tiles_height) = tiles_for_view(px_width, px_height, tile_codec);
+            self.tiled_or_linear = TileCorruptorTiledOrLinear::Tiled {
+                tiles_width,
+                tiles_height,
+                tile_codec,
+            };
+            self.resize();
+            self.render();
+        } else {
+            let (width, height) = match self.tiled_or_linear {
+                TileCorruptorTiledOrLinear::Linear { width, height, .. } => (width, height),
+                _ => (256, 256),
+            };
+            self.tiled_or_linear = TileCorruptorTiledOrLinear::Linear {
+                width,
+                height,
+                lin_codec: codec.lin_codec.unwrap(),
+            };
+            self.resize();
+            self.render();
+        }
+    }
+
+    fn num_palette_colors(&self) -> usize {
+        match self.tiled_or_linear {
+            TileCorruptorTiledOrLinear::Tiled { tile_codec, .. } => tile_codec.num_palette_colors(),
+            TileCorruptorTiledOrLinear::Linear { lin_codec, .. } => lin_codec.num_palette_colors(),
+        }
+    }
+
+    fn view_dims(&self) -> (usize, usize) {
+        match self.tiled_or_linear {
+            TileCorruptorTiledOrLinear::Tiled {
+                tiles_width,
+                tiles_height,
+                tile_codec,
+            } => (
+                tiles_width * tile_codec.tile_width(),
+                tiles_height * tile_codec.tile_height(),
+            ),
+            TileCorruptorTiledOrLinear::Linear { width, height, .. } => (width, height),
+        }
+    }
+
+    fn canvas_to_px(&self, canvas_x: f64, canvas_y: f64) -> Option<(usize, usize)> {
+        if canvas_x < 0.0 || canvas_y < 0.0 {
+            return None;
+        }
+        let x = (canvas_x / self.px_scale) as usize;
+        let y = (canvas_y / self.px_scale) as usize;
+        let (w, h) = self.view_dims();
+        if x < w && y < h {
+            Some((x, y))
+        } else {
+            None
+        }
+    }
+
+    /// Encodes palette index `color` at view pixel (`x`, `y`).
+    /// Returns `false` if nothing was written, either because the color
+    /// does not fit in the codec or because the pixel lies past the end of the data.
+    fn write_px(&mut self, x: usize, y: usize, color: u8) -> bool {
+        if color as usize >= self.num_palette_colors() {
+            return false;
+        }
+
+        let (px_bits_start, touched_bits) = match self.tiled_or_linear {
+            TileCorruptorTiledOrLinear::Tiled {
+                tiles_width,
+                tiles_height,
+                tile_codec,
+            } => {
+                let tile_i = self.arrangement.tile_index(
+                    tiles_width,
+                    tiles_height,
+                    x / tile_codec.tile_width(),
+                    y / tile_codec.tile_height(),
+                );
+                (
+                    tile_i * tile_codec.bits_per_tile(),
+                    tile_codec.tile_bit_ranges(tile_i),
+                )
+            }
+            TileCorruptorTiledOrLinear::Linear {
+                width, lin_codec, ..
+            } => {
+                let bits_per_row = lin_codec.bits_per_row(width);
+                #[allow(clippy::single_range_in_vec_init)]
+                let row = vec![(y * bits_per_row)..((y + 1) * bits_per_row)];
+                (y * bits_per_row, row)
+            }
+        };
+        let px_bits_start = self.data_bit_off + px_bits_start;
+        // nothing to record for tiles past the end; the codec reports pixels that only partly fit
+        if px_bits_start >= self.data.len() * 8 {
+            return false;
+        }
+        for bits in touched_bits {
+            self.history.record_bits(
+                &self.data,
+                (self.data_bit_off + bits.start)..(self.data_bit_off + bits.end),
+            );
+        }
+
+        match self.tiled_or_linear {
+            TileCorruptorTiledOrLinear::Tiled { tile_codec, .. } => {
+                // the tile could be anywhere depending on the arrangement,
+                // so address it directly as a lone 1x1 view
+                tile_codec.write_px(
+                    &mut self.data[(px_bits_start / 8)..],
+                    (px_bits_start % 8) as u8,
+                    1,
+                    x % tile_codec.tile_width(),
+                    y % tile_codec.tile_height(),
+                    color,
+                )
+            }
+            TileCorruptorTiledOrLinear::Linear {
+                width, lin_codec, ..
+            } => lin_codec.write_px(
+                &mut self.data[(self.data_bit_off / 8)..],
+                (self.data_bit_off % 8) as u8,
+                width,
+                x,
+                y,
+                color,
+            ),
+        }
+    }
+
+    /// Turns everything written since the last call into one undo step,
+    /// unless JS has grouped several operations together with `begin_edit`
+    fn finish_edit(&mut self) {
+        if !self.edit_group_open {
+            self.history.commit(&self.data);
+        }
+    }
+
+    fn render_to(&self, r: &mut dyn AbstractPixelTarget) {
+        match self.tiled_or_linear {
+            TileCorruptorTiledOrLinear::Tiled {
+                tiles_width,
+                tiles_height,
+                tile_codec,
+            } => {
+                self.arrangement.render(
+                    tile_codec,
+                    r,
+                    &self.data[(self.data_bit_off / 8)..],
+                    (self.data_bit_off % 8) as u8,
+                    tiles_width,
+                    tiles_height,
+                );
+            }
+            TileCorruptorTiledOrLinear::Linear {
+                width,
+                height,
+                lin_codec,
+            } => {
+                lin_codec.render(
+                    r,
+                    &self.data[(self.data_bit_off / 8)..],
+                    (self.data_bit_off % 8) as u8,
+                    width,
+                    height,
+                );
+            }
+        }
+    }
+
+    /// Redraws only the tiles (or rows, for linear codecs) containing the given pixels
+    fn rerender_pxs(&self, pxs: &[(usize, usize)]) {
+        match self.tiled_or_linear {
+            TileCorruptorTiledOrLinear::Tiled {
+                tiles_width,
+                tiles_height,
+                tile_codec,
+            } => {
+                let tile_w = tile_codec.tile_width();
+                let tile_h = tile_codec.tile_height();
+                let tiles = pxs
+                    .iter()
+                    .map(|&(x, y)| (x / tile_w, y / tile_h))
+                    .collect::<BTreeSet<_>>();
+
+                for (tile_x, tile_y) in tiles {
+                    let bit_off = self.data_bit_off
+                        + self.arrangement.tile_bit_offset(
+                            tile_codec,
+                            tiles_width,
+                            tiles_height,
+                            tile_x,
+                            tile_y,
+                        );
+                    if bit_off >= self.data.len() * 8 {
+                        continue;
+                    }
+
+                    let mut px = vec![0u8; tile_w * tile_h * 4];
+                    tile_codec.render(
+                        &mut RgbaPixelWriter {
+                            w: tile_w,
+                            px: &mut px,
+                            pal: &self.pal,
+                        },
+                        &self.data[(bit_off / 8)..],
+                        (bit_off % 8) as u8,
+                        1,
+                        1,
+                    );
+                    self.blit(&px, tile_x * tile_w, tile_y * tile_h, tile_w, tile_h);
+                }
+            }
+            TileCorruptorTiledOrLinear::Linear {
+                width, lin_codec, ..
+            } => {
+                let rows = pxs.iter().map(|&(_, y)| y).collect::<BTreeSet<_>>();
+
+                for y in rows {
+                    let bit_off = self.data_bit_off + y * lin_codec.bits_per_row(width);
+
+                    let mut px = vec![0u8; width * 4];
+                    lin_codec.render(
+                        &mut RgbaPixelWriter {
+                            w: width,
+                            px: &mut px,
+                            pal: &self.pal,
+                        },
+                        &self.data[(bit_off / 8)..],
+                        (bit_off % 8) as u8,
+                        width,
+                        1,
+                    );
+                    self.blit(&px, 0, y, width, 1);
+                }
+            }
+        }
+    }
+
+    /// Copies an RGBA buffer onto the canvas in a single call
+    fn blit(&self, px: &[u8], x: usize, y: usize, w: usize, h: usize) {
+        let img =
+            ImageData::new_with_u8_clamped_array_and_sh(Clamped(px), w as u32, h as u32).unwrap();
+        self.ctx.put_image_data(&img, x as f64, y as f64).unwrap();
+    }
+}
+
 #[wasm_bindgen(start)]
 pub fn main_js() {
     #[cfg(debug_assertions)]
     console_error_panic_hook::set_once();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switch_keeps_px_size() {
+        assert_eq!(tiles_for_view(256, 128, &NESGraphics::new()), (32, 16));
+        assert_eq!(
+            tiles_for_view(256, 256, &PCESpriteGraphics::wrap(Default::default())),
+            (16, 16)
+        );
+        // never less than one tile
+        assert_eq!(
+            tiles_for_view(256, 256, &SNESMode7MapGraphics::new()),
+            (1, 1)
+        );
+        assert_eq!(tiles_for_view(1024, 1024, &NESGraphics::new()), (128, 128));
+    }
+
+    #[test]
+    fn load_codecs_twice() {
+        let text = "[a]\nbpp = 2\n\n[b]\nbpp = 2\n";
+        assert_eq!(wasm_load_codecs(text).ok(), Some(2));
+        let first = LOADED_GRAPHICS_CODECS.with_borrow(|loaded| loaded.clone());
+        assert!(std::ptr::eq(first[0].1, first[1].1));
+        assert_eq!(wasm_load_codecs(text).ok(), Some(2));
+        LOADED_GRAPHICS_CODECS.with_borrow(|loaded| {
+            assert_eq!(loaded.len(), 2);
+            assert!(std::ptr::eq(loaded[0].0, first[0].0));
+            assert!(std::ptr::eq(loaded[0].1, first[0].1));
+        });
+        LOADED_DESCRIPTORS.with_borrow(|descs| assert_eq!(descs.len(), 1));
+        assert_eq!(
+            wasm_get_graphics_codecs().len(),
+            BUILTIN_GRAPHICS_CODECS.len() + 2
+        );
+    }
+}
@@ -15,6 +15,9 @@ pub trait LinearCodec {
         w: usize,
         h: usize,
     );
+    /// Returns `false` if the pixel lies past the end of `bytes`
+    fn write_px(&self, bytes: &mut [u8], bit_offs: u8, w: usize, x: usize, y: usize, i: u8)
+        -> bool;
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -66,7 +69,9 @@ impl<DataBitOrder: BitOrder, const BPP: usize, const PX_PAD: usize, const ROW_PA
                 let mut px = [0u8; 1];
                 let px_bv = px.view_bits_mut::<Lsb0>();
                 for b_i in 0..BPP {
-                    let bit_idx = bit_offs as usize + y * data_bits_per_row + x * BPP + b_i;
+                    // pixels are BPP + PX_PAD apart, like bits_per_row and write_px count them
+                    let bit_idx =
+                        bit_offs as usize + y * data_bits_per_row + x * (BPP + PX_PAD) + b_i;
                     if bit_idx >= bits.len() {
                         return;
                     }
@@ -76,6 +81,33 @@ impl<DataBitOrder: BitOrder, const BPP: usize, const PX_PAD: usize, const ROW_PA
             }
         }
     }
+
+    fn write_px(
+        &self,
+        bytes: &mut [u8],
+        bit_offs: u8,
+        w: usize,
+        x: usize,
+        y: usize,
+        i: u8,
+    ) -> bool {
+        debug_assert!(BPP <= 8);
+        let bits = bytes.view_bits_mut::<DataBitOrder>();
+
+        let data_bits_per_row = (BPP + PX_PAD) * w + ROW_PAD;
+
+        let px = [i];
+        let px_bv = px.view_bits::<Lsb0>();
+        let bit_idx =
+            |b_i: usize| bit_offs as usize + y * data_bits_per_row + x * (BPP + PX_PAD) + b_i;
+        if (0..BPP).any(|b_i| bit_idx(b_i) >= bits.len()) {
+            return false;
+        }
+        for b_i in 0..BPP {
+            bits.set(bit_idx(b_i), px_bv[b_i]);
+        }
+        true
+    }
 }
 
 pub type _1bppMsbFirstGraphics = NbppPalettedGraphics<Msb0, 1, 0, 0>;
@@ -89,4 +121,54 @@ mod tests {
     fn ensure_obj_safe() {
         let _: &dyn LinearCodec;
     }
+
+    #[test]
+    fn write_1bpp() {
+        let mut data = [0u8; 2];
+        _1bppMsbFirstGraphics::new().write_px(&mut data, 0, 8, 1, 1, 1);
+        assert_eq!(data, [0x00, 0x40]);
+        _1bppLsbFirstGraphics::new().write_px(&mut data, 0, 8, 1, 0, 1);
+        assert_eq!(data, [0x02, 0x40]);
+        _1bppLsbFirstGraphics::new().write_px(&mut data, 0, 8, 6, 1, 0);
+        assert_eq!(data, [0x02, 0x00]);
+    }
+
+    #[test]
+    fn render_px_pad() {
+        // 2bpp pixels followed by 2 bits of padding: the second pixel is the low nibble
+        let codec = NbppPalettedGraphics::<Msb0, 2, 2, 0>::new();
+        let mut px = [0u8; 2];
+        codec.render(
+            &mut crate::InMemoryPixelWriter { w: 2, px: &mut px },
+            &[0b1000_0111],
+            0,
+            2,
+            1,
+        );
+        assert_eq!(px, [1, 2]);
+
+        // and it reads back what write_px stored
+        let mut data = [0u8; 1];
+        codec.write_px(&mut data, 0, 2, 0, 0, 1);
+        codec.write_px(&mut data, 0, 2, 1, 0, 2);
+        let mut px = [0u8; 2];
+        codec.render(
+            &mut crate::InMemoryPixelWriter { w: 2, px: &mut px },
+            &data,
+            0,
+            2,
+            1,
+        );
+        assert_eq!(px, [1, 2]);
+    }
+
+    #[test]
+    fn write_out_of_bounds() {
+        let mut data = [0u8; 1];
+        assert!(!_1bppMsbFirstGraphics::new().write_px(&mut data, 0, 8, 0, 1, 1));
+        assert_eq!(data, [0x00]);
+        // the third pixel straddles the end of the data
+        assert!(!NbppPalettedGraphics::<Msb0, 3, 0, 0>::new().write_px(&mut data, 0, 8, 2, 0, 7));
+        assert_eq!(data, [0x00]);
+    }
 }
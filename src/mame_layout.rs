@@ -0,0 +1,419 @@
+use std::fmt;
+
+use crate::gfx_layout::{GfxLayout, GfxLayoutError};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MameLayoutError {
+    Unexpected { line: usize, found: String },
+    UnexpectedEnd,
+    UnknownName { line: usize, name: String },
+    DivisionByZero { line: usize },
+    OutOfRange { line: usize },
+    WrongPlaneCount { expected: usize, got: usize },
+    Layout(GfxLayoutError),
+}
+impl fmt::Display for MameLayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MameLayoutError::Unexpected { line, found } => {
+                write!(f, "line {}: unexpected `{}`", line, found)
+            }
+            MameLayoutError::UnexpectedEnd => write!(f, "unexpected end of layout"),
+            MameLayoutError::UnknownName { line, name } => {
+                write!(f, "line {}: unknown name `{}`", line, name)
+            }
+            MameLayoutError::DivisionByZero { line } => {
+                write!(f, "line {}: division by zero", line)
+            }
+            MameLayoutError::OutOfRange { line } => {
+                write!(f, "line {}: value is negative or too large", line)
+            }
+            MameLayoutError::WrongPlaneCount { expected, got } => {
+                write!(
+                    f,
+                    "layout has {} planes, but {} plane offsets",
+                    expected, got
+                )
+            }
+            MameLayoutError::Layout(e) => e.fmt(f),
+        }
+    }
+}
+impl std::error::Error for MameLayoutError {}
+impl From<GfxLayoutError> for MameLayoutError {
+    fn from(e: GfxLayoutError) -> Self {
+        MameLayoutError::Layout(e)
+    }
+}
+
+/// Splits C source into (token, line number) pairs, dropping comments
+fn tokenize(text: &str) -> Vec<(&str, usize)> {
+    let mut ret = Vec::new();
+    let mut line = 1;
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        let len = if c == '\n' {
+            line += 1;
+            1
+        } else if c.is_whitespace() {
+            c.len_utf8()
+        } else if rest.starts_with("//") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if rest.starts_with("/*") {
+            let len = rest.find("*/").map_or(rest.len(), |i| i + 2);
+            line += rest[..len].matches('\n').count();
+            len
+        } else {
+            let len = if c.is_ascii_alphanumeric() || c == '_' {
+                rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len())
+            } else {
+                c.len_utf8()
+            };
+            ret.push((&rest[..len], line));
+            len
+        };
+        rest = &rest[len..];
+    }
+
+    ret
+}
+
+fn parse_number(tok: &str) -> Option<i64> {
+    let tok = tok.trim_end_matches(['u', 'U', 'l', 'L']);
+    match tok.strip_prefix("0x").or_else(|| tok.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => tok.parse().ok(),
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<(&'a str, usize)>,
+    pos: usize,
+    region_bits: i64,
+}
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(|&(tok, _)| tok)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map_or(1, |&(_, line)| line)
+    }
+
+    fn unexpected(&self) -> MameLayoutError {
+        match self.peek() {
+            Some(tok) => MameLayoutError::Unexpected {
+                line: self.line(),
+                found: tok.to_owned(),
+            },
+            None => MameLayoutError::UnexpectedEnd,
+        }
+    }
+
+    fn expect(&mut self, tok: &str) -> Result<(), MameLayoutError> {
+        if self.peek() != Some(tok) {
+            return Err(self.unexpected());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn eat(&mut self, tok: &str) -> bool {
+        let found = self.peek() == Some(tok);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expr(&mut self) -> Result<i64, MameLayoutError> {
+        let mut val = self.term()?;
+        loop {
+            let line = self.line();
+            val = if self.eat("+") {
+                val.checked_add(self.term()?)
+            } else if self.eat("-") {
+                val.checked_sub(self.term()?)
+            } else {
+                return Ok(val);
+            }
+            .ok_or(MameLayoutError::OutOfRange { line })?;
+        }
+    }
+
+    fn term(&mut self) -> Result<i64, MameLayoutError> {
+        let mut val = self.unary()?;
+        loop {
+            let line = self.line();
+            let op = match self.peek() {
+                Some(op @ ("*" | "/" | "%")) => op,
+                _ => return Ok(val),
+            };
+            self.pos += 1;
+            let rhs = self.unary()?;
+            if op != "*" && rhs == 0 {
+                return Err(MameLayoutError::DivisionByZero { line });
+            }
+            val = match op {
+                "*" => val.checked_mul(rhs),
+                "/" => val.checked_div(rhs),
+                _ => val.checked_rem(rhs),
+            }
+            .ok_or(MameLayoutError::OutOfRange { line })?;
+        }
+    }
+
+    fn unary(&mut self) -> Result<i64, MameLayoutError> {
+        if self.eat("-") {
+            let line = self.line();
+            return self
+                .unary()?
+                .checked_neg()
+                .ok_or(MameLayoutError::OutOfRange { line });
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<i64, MameLayoutError> {
+        let line = self.line();
+        if self.eat("(") {
+            let val = self.expr()?;
+            self.expect(")")?;
+            return Ok(val);
+        }
+        if self.eat("RGN_FRAC") {
+            // a fraction of the whole graphics region
+            self.expect("(")?;
+            let num = self.expr()?;
+            self.expect(",")?;
+            let den = self.expr()?;
+            self.expect(")")?;
+            if den == 0 {
+                return Err(MameLayoutError::DivisionByZero { line });
+            }
+            return self
+                .region_bits
+                .checked_mul(num)
+                .map(|bits| bits / den)
+                .ok_or(MameLayoutError::OutOfRange { line });
+        }
+
+        let tok = self.peek().ok_or(MameLayoutError::UnexpectedEnd)?;
+        if tok.starts_with(|c: char| c.is_ascii_digit()) {
+            self.pos += 1;
+            return parse_number(tok).ok_or(MameLayoutError::Unexpected {
+                line,
+                found: tok.to_owned(),
+            });
+        }
+        if tok.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            return Err(MameLayoutError::UnknownName {
+                line,
+                name: tok.to_owned(),
+            });
+        }
+        Err(self.unexpected())
+    }
+
+    fn offset(&mut self) -> Result<usize, MameLayoutError> {
+        let line = self.line();
+        self.expr()?
+            .try_into()
+            .map_err(|_| MameLayoutError::OutOfRange { line })
+    }
+
+    /// Parses `{ a, b, STEP4(c, d), ... }`
+    fn offset_list(&mut self) -> Result<Vec<usize>, MameLayoutError> {
+        let mut ret = Vec::new();
+        self.expect("{")?;
+        while !self.eat("}") {
+            let step_count = match self.peek() {
+                Some("STEP2") => Some(2),
+                Some("STEP4") => Some(4),
+                Some("STEP8") => Some(8),
+                Some("STEP16") => Some(16),
+                Some("STEP32") => Some(32),
+                _ => None,
+            };
+            if let Some(count) = step_count {
+                let line = self.line();
+                self.pos += 1;
+                self.expect("(")?;
+                let start = self.offset()?;
+                self.expect(",")?;
+                let step = self.offset()?;
+                self.expect(")")?;
+                for i in 0..count {
+                    let offs = step
+                        .checked_mul(i)
+                        .and_then(|o| o.checked_add(start))
+                        .ok_or(MameLayoutError::OutOfRange { line })?;
+                    ret.push(offs);
+                }
+            } else {
+                ret.push(self.offset()?);
+            }
+            if !self.eat(",") {
+                self.expect("}")?;
+                break;
+            }
+        }
+        Ok(ret)
+    }
+}
+
+/// Parses a `gfx_layout` struct as written in MAME drivers, e.g.
+///
+/// ```text
+/// static const gfx_layout charlayout =
+/// {
+///     8,8,
+///     RGN_FRAC(1,2),
+///     2,
+///     { RGN_FRAC(1,2), 0 },
+///     { STEP8(0,1) },
+///     { STEP8(0,8) },
+///     8*8
+/// };
+/// ```
+///
+/// `RGN_FRAC` is resolved against `region_len` bytes of data.
+/// The number of tiles is ignored, the view shows as many as fit.
+pub fn parse_mame_layout(text: &str, region_len: usize) -> Result<GfxLayout, MameLayoutError> {
+    let tokens = tokenize(text);
+    // skip the declaration
+    let start = tokens.iter().position(|&(tok, _)| tok == "{").unwrap_or(0);
+    let mut p = Parser {
+        tokens,
+        pos: start,
+        region_bits: (region_len as i64).saturating_mul(8),
+    };
+
+    p.expect("{")?;
+    let width = p.offset()?;
+    p.expect(",")?;
+    let height = p.offset()?;
+    p.expect(",")?;
+    let _num_tiles = p.expr()?;
+    p.expect(",")?;
+    let planes = p.offset()?;
+    p.expect(",")?;
+    let plane_offsets = p.offset_list()?;
+    p.expect(",")?;
+    let x_offsets = p.offset_list()?;
+    p.expect(",")?;
+    let y_offsets = p.offset_list()?;
+    p.expect(",")?;
+    let char_increment = p.offset()?;
+    p.eat(",");
+    p.expect("}")?;
+
+    if plane_offsets.len() != planes {
+        return Err(MameLayoutError::WrongPlaneCount {
+            expected: planes,
+            got: plane_offsets.len(),
+        });
+    }
+    Ok(GfxLayout::new(
+        width,
+        height,
+        plane_offsets,
+        x_offsets,
+        y_offsets,
+        char_increment,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfx_layout::tests::step;
+
+    #[test]
+    fn parse() {
+        let text = "
+            static const gfx_layout spritelayout =
+            {
+                16,16,  /* 16*16 sprites */
+                RGN_FRAC(1,4),
+                4,
+                { RGN_FRAC(3,4), RGN_FRAC(2,4), RGN_FRAC(1,4), 0 },
+                { STEP8(0,1), STEP8(8*8,1) },
+                { STEP8(0,8), STEP8(16*8, 8) }, // two halves
+                32*8
+            };
+        ";
+        let layout = parse_mame_layout(text, 0x1000).unwrap();
+        assert_eq!(
+            layout,
+            GfxLayout::new(
+                16,
+                16,
+                vec![3 * 0x1000 * 2, 2 * 0x1000 * 2, 0x1000 * 2, 0],
+                [step(0, 1, 8), step(64, 1, 8)].concat(),
+                [step(0, 8, 8), step(128, 8, 8)].concat(),
+                256
+            )
+            .unwrap()
+        );
+
+        let text = "{ 4, 1, 0x10, 2, { 4, 0 }, { 3, 2, (1+0), 0, }, { 0 }, 8, }";
+        assert_eq!(
+            parse_mame_layout(text, 0).unwrap(),
+            GfxLayout::new(4, 1, vec![4, 0], vec![3, 2, 1, 0], vec![0], 8).unwrap()
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse_mame_layout("{ 8, 8, 1, 1, { 0 }, { STEP8(0,1) }, { STEP8(0,8) } }", 0),
+            Err(MameLayoutError::Unexpected {
+                line: 1,
+                found: "}".to_owned()
+            })
+        );
+        assert_eq!(
+            parse_mame_layout("{ 8,\n8, FOO, 1", 0),
+            Err(MameLayoutError::UnknownName {
+                line: 2,
+                name: "FOO".to_owned()
+            })
+        );
+        assert_eq!(
+            parse_mame_layout("{ 8, 8, 1, 1, { RGN_FRAC(1,0) }", 0),
+            Err(MameLayoutError::DivisionByZero { line: 1 })
+        );
+        assert_eq!(
+            parse_mame_layout("{ 8, 8, 1, 1, { 0 - 8 }", 0),
+            Err(MameLayoutError::OutOfRange { line: 1 })
+        );
+        assert_eq!(
+            parse_mame_layout("{ 8, 8, 1, 1, { 0 },\n{ STEP8(0, 0x7fffffffffffffff) }", 0),
+            Err(MameLayoutError::OutOfRange { line: 2 })
+        );
+        assert_eq!(
+            parse_mame_layout("{ 1, 1, 1, 2, { 0 }, { 0 }, { 0 }, 1 }", 0),
+            Err(MameLayoutError::WrongPlaneCount {
+                expected: 2,
+                got: 1
+            })
+        );
+        assert_eq!(
+            parse_mame_layout("{ 2, 1, 1, 1, { 0 }, { 0 }, { 0 }, 1 }", 0),
+            Err(MameLayoutError::Layout(GfxLayoutError::WrongXOffsets {
+                expected: 2,
+                got: 1
+            }))
+        );
+        assert_eq!(
+            parse_mame_layout("{ 8, 8", 0),
+            Err(MameLayoutError::UnexpectedEnd)
+        );
+    }
+}
@@ -0,0 +1,286 @@
+use super::{
+    check_footer, push_footer, read_vlq, read_vlq_usize, write_vlq, ChecksumKind, PatchError,
+};
+
+pub const BPS_MAGIC: &[u8] = b"BPS1";
+
+const BPS_SOURCE_READ: u64 = 0;
+const BPS_TARGET_READ: u64 = 1;
+const BPS_SOURCE_COPY: u64 = 2;
+const BPS_TARGET_COPY: u64 = 3;
+
+// switching from literal data back to reading the source costs command bytes,
+// so very short matches are not worth it
+const BPS_MIN_SOURCE_READ: usize = 4;
+
+fn push_command(out: &mut Vec<u8>, cmd: u64, len: usize) {
+    write_vlq(out, ((len as u64 - 1) << 2) | cmd);
+}
+
+/// Creates a BPS patch which turns `source` into `target`.
+/// This only uses in-place source reads and literal data, which is ideal
+/// for edits that don't move anything around.
+pub fn create_bps(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut ret = BPS_MAGIC.to_vec();
+    write_vlq(&mut ret, source.len() as u64);
+    write_vlq(&mut ret, target.len() as u64);
+    // no metadata
+    write_vlq(&mut ret, 0);
+
+    let matching_at = |i: usize| {
+        target[i..]
+            .iter()
+            .zip(source.get(i..).unwrap_or_default())
+            .take_while(|(a, b)| a == b)
+            .count()
+    };
+
+    let mut i = 0;
+    while i < target.len() {
+        let m = matching_at(i);
+        if m > 0 {
+            push_command(&mut ret, BPS_SOURCE_READ, m);
+            i += m;
+            continue;
+        }
+
+        let literal_start = i;
+        while i < target.len() && matching_at(i) < BPS_MIN_SOURCE_READ.min(target.len() - i) {
+            i += 1;
+        }
+        push_command(&mut ret, BPS_TARGET_READ, i - literal_start);
+        ret.extend_from_slice(&target[literal_start..i]);
+    }
+
+    push_footer(&mut ret, source, target);
+    ret
+}
+
+fn read_relative_offset(patch: &[u8], pos: &mut usize, base: usize) -> Result<usize, PatchError> {
+    let x = read_vlq(patch, pos)?;
+    let delta = (x >> 1) as usize;
+    if x & 1 != 0 {
+        base.checked_sub(delta)
+    } else {
+        base.checked_add(delta)
+    }
+    .ok_or(PatchError::Corrupt)
+}
+
+/// Applies a BPS patch, verifying that `data` is the correct source file
+pub fn apply_bps(data: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if !patch.starts_with(BPS_MAGIC) {
+        return Err(PatchError::UnknownFormat);
+    }
+    let footer = check_footer(patch, BPS_MAGIC.len())?;
+    let body_end = patch.len() - 12;
+
+    let data_crc = crc32fast::hash(data);
+    if data_crc != footer.source_crc {
+        return Err(PatchError::ChecksumMismatch {
+            kind: ChecksumKind::Source,
+            expected: footer.source_crc,
+            got: data_crc,
+        });
+    }
+
+    let mut pos = BPS_MAGIC.len();
+    let source_len = read_vlq_usize(patch, &mut pos)?;
+    let target_len = read_vlq_usize(patch, &mut pos)?;
+    let metadata_len = read_vlq_usize(patch, &mut pos)?;
+    pos = pos.checked_add(metadata_len).ok_or(PatchError::Corrupt)?;
+    if source_len != data.len() || pos > body_end {
+        return Err(PatchError::Corrupt);
+    }
+
+    // the length comes from the patch, so don't trust it for the allocation
+    let mut out = Vec::with_capacity(target_len.min(data.len() + patch.len()));
+    let mut source_rel = 0;
+    let mut target_rel = 0;
+    while pos < body_end {
+        let x = read_vlq(patch, &mut pos)?;
+        let len = usize::try_from((x >> 2) + 1).map_err(|_| PatchError::Corrupt)?;
+        let out_end = out.len().checked_add(len).ok_or(PatchError::Corrupt)?;
+        if out_end > target_len {
+            return Err(PatchError::Corrupt);
+        }
+
+        match x & 3 {
+            BPS_SOURCE_READ => {
+                let src = data.get(out.len()..out_end).ok_or(PatchError::Corrupt)?;
+                out.extend_from_slice(src);
+            }
+            BPS_TARGET_READ => {
+                let src = pos
+                    .checked_add(len)
+                    .filter(|&end| end <= body_end)
+                    .map(|end| &patch[pos..end])
+                    .ok_or(PatchError::Truncated)?;
+                out.extend_from_slice(src);
+                pos += len;
+            }
+            BPS_SOURCE_COPY => {
+                source_rel = read_relative_offset(patch, &mut pos, source_rel)?;
+                let source_end = source_rel.checked_add(len).ok_or(PatchError::Corrupt)?;
+                let src = data
+                    .get(source_rel..source_end)
+                    .ok_or(PatchError::Corrupt)?;
+                out.extend_from_slice(src);
+                source_rel = source_end;
+            }
+            BPS_TARGET_COPY => {
+                target_rel = read_relative_offset(patch, &mut pos, target_rel)?;
+                // copies may overlap the data being written
+                for _ in 0..len {
+                    let b = *out.get(target_rel).ok_or(PatchError::Corrupt)?;
+                    out.push(b);
+                    target_rel += 1;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+    if out.len() != target_len {
+        return Err(PatchError::Corrupt);
+    }
+
+    let out_crc = crc32fast::hash(&out);
+    if out_crc != footer.target_crc {
+        return Err(PatchError::ChecksumMismatch {
+            kind: ChecksumKind::Target,
+            expected: footer.target_crc,
+            got: out_crc,
+        });
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_patch(source: &[u8], target: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_MAGIC.to_vec();
+        write_vlq(&mut patch, source.len() as u64);
+        write_vlq(&mut patch, target.len() as u64);
+        write_vlq(&mut patch, 0);
+        patch.extend_from_slice(body);
+        push_footer(&mut patch, source, target);
+        patch
+    }
+
+    #[test]
+    fn roundtrip() {
+        let source = (0..1000).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+        let mut target = source.clone();
+        target[0] = 0xff;
+        target[100..110].fill(0);
+        target[112] = 1;
+        target.truncate(900);
+
+        let patch = create_bps(&source, &target);
+        assert_eq!(apply_bps(&source, &patch), Ok(target.clone()));
+
+        target.extend_from_slice(&[1; 300]);
+        let patch = create_bps(&source, &target);
+        assert_eq!(apply_bps(&source, &patch), Ok(target));
+    }
+
+    #[test]
+    fn known_patch() {
+        let patch = create_bps(&[1, 2, 3, 4, 5, 6, 7, 8], &[1, 2, 9, 9, 5, 6, 7, 8]);
+        assert_eq!(
+            &patch[4..(patch.len() - 12)],
+            b"\x88\x88\x80\x84\x85\x09\x09\x8c"
+        );
+    }
+
+    #[test]
+    fn copy_commands() {
+        let source = b"abcdefgh";
+        let target = b"efgabcxxxxxx";
+        let mut body = Vec::new();
+        // "efg" from source offset 4
+        push_command(&mut body, BPS_SOURCE_COPY, 3);
+        write_vlq(&mut body, 4 << 1);
+        // "abc" from source offset 0, i.e. 7 bytes backwards
+        push_command(&mut body, BPS_SOURCE_COPY, 3);
+        write_vlq(&mut body, (7 << 1) | 1);
+        push_command(&mut body, BPS_TARGET_READ, 1);
+        body.push(b'x');
+        // overlapping copy of the "x" just written
+        push_command(&mut body, BPS_TARGET_COPY, 5);
+        write_vlq(&mut body, 6 << 1);
+
+        let patch = make_patch(source, target, &body);
+        assert_eq!(apply_bps(source, &patch), Ok(target.to_vec()));
+    }
+
+    #[test]
+    fn wrong_source() {
+        let patch = create_bps(&[1, 2, 3], &[1, 2, 4]);
+        assert_eq!(
+            apply_bps(&[1, 2, 5], &patch),
+            Err(PatchError::ChecksumMismatch {
+                kind: ChecksumKind::Source,
+                expected: crc32fast::hash(&[1, 2, 3]),
+                got: crc32fast::hash(&[1, 2, 5]),
+            })
+        );
+    }
+
+    #[test]
+    fn wrong_target() {
+        let mut body = Vec::new();
+        push_command(&mut body, BPS_SOURCE_READ, 3);
+        let patch = make_patch(&[1, 2, 3], &[1, 2, 4], &body);
+        assert!(matches!(
+            apply_bps(&[1, 2, 3], &patch),
+            Err(PatchError::ChecksumMismatch {
+                kind: ChecksumKind::Target,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        let mut body = Vec::new();
+        push_command(&mut body, BPS_TARGET_COPY, 1);
+        write_vlq(&mut body, 0);
+        let patch = make_patch(&[], &[0], &body);
+        assert_eq!(apply_bps(&[], &patch), Err(PatchError::Corrupt));
+    }
+
+    #[test]
+    fn malicious_lengths() {
+        let source = [1, 2, 3, 4];
+        let huge = usize::MAX >> 3;
+
+        // a target far bigger than anything that could be allocated
+        let mut patch = BPS_MAGIC.to_vec();
+        write_vlq(&mut patch, source.len() as u64);
+        write_vlq(&mut patch, u64::MAX >> 2);
+        write_vlq(&mut patch, 0);
+        push_command(&mut patch, BPS_SOURCE_READ, huge);
+        push_footer(&mut patch, &source, &[]);
+        assert_eq!(apply_bps(&source, &patch), Err(PatchError::Corrupt));
+
+        // lengths and offsets that overflow usize arithmetic on 32-bit targets
+        for cmd in [BPS_SOURCE_COPY, BPS_TARGET_READ] {
+            let mut patch = BPS_MAGIC.to_vec();
+            write_vlq(&mut patch, source.len() as u64);
+            write_vlq(&mut patch, huge as u64 + 4);
+            write_vlq(&mut patch, 0);
+            push_command(&mut patch, BPS_SOURCE_READ, 4);
+            push_command(&mut patch, cmd, huge);
+            write_vlq(&mut patch, (huge as u64) << 1);
+            push_footer(&mut patch, &source, &[]);
+            assert!(matches!(
+                apply_bps(&source, &patch),
+                Err(PatchError::Corrupt | PatchError::Truncated)
+            ));
+        }
+    }
+}
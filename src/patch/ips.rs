@@ -0,0 +1,308 @@
+use std::ops::Range;
+
+use super::PatchError;
+use crate::history::diff_ranges;
+
+pub const IPS_MAX_LEN: usize = 1 << 24;
+// the truncation extension stores the new length in 24 bits
+const IPS_MAX_TRUNCATED_LEN: usize = IPS_MAX_LEN - 1;
+
+pub const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+// a record at this offset would be mistaken for the end marker
+const IPS_EOF_OFFSET: usize = 0x454F46;
+const IPS_MAX_RECORD_LEN: usize = 0xFFFF;
+// an RLE record is 8 bytes, so shorter runs are cheaper to store inline
+const IPS_MIN_RLE_LEN: usize = 9;
+// starting a new record costs a 5 byte header, so tiny gaps are cheaper to rewrite
+const IPS_MERGE_GAP: usize = 5;
+
+fn push_header(out: &mut Vec<u8>, offset: usize, len: usize) {
+    out.extend_from_slice(&(offset as u32).to_be_bytes()[1..]);
+    out.extend_from_slice(&(len as u16).to_be_bytes());
+}
+
+fn push_raw(out: &mut Vec<u8>, new: &[u8], r: Range<usize>) {
+    if r.is_empty() {
+        return;
+    }
+
+    let mut start = r.start;
+    if start == IPS_EOF_OFFSET {
+        start -= 1;
+    }
+    while start < r.end {
+        let mut end = r.end.min(start + IPS_MAX_RECORD_LEN);
+        if end == IPS_EOF_OFFSET && end < r.end {
+            end -= 1;
+        }
+        push_header(out, start, end - start);
+        out.extend_from_slice(&new[start..end]);
+        start = end;
+    }
+}
+
+fn push_rle(out: &mut Vec<u8>, new: &[u8], mut r: Range<usize>) {
+    if r.start == IPS_EOF_OFFSET {
+        push_raw(out, new, r.start..(r.start + 1));
+        r.start += 1;
+    }
+    push_header(out, r.start, 0);
+    out.extend_from_slice(&(r.len() as u16).to_be_bytes());
+    out.push(new[r.start]);
+}
+
+/// Creates an IPS patch which turns `orig` into `new`
+pub fn create_ips(orig: &[u8], new: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if new.len() > IPS_MAX_LEN {
+        return Err(PatchError::TooLarge {
+            len: new.len(),
+            max_len: IPS_MAX_LEN,
+        });
+    }
+    if new.len() < orig.len() && new.len() > IPS_MAX_TRUNCATED_LEN {
+        return Err(PatchError::TooLarge {
+            len: new.len(),
+            max_len: IPS_MAX_TRUNCATED_LEN,
+        });
+    }
+
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for r in diff_ranges(orig, new) {
+        // anything past the end of `new` is handled by truncation
+        let r = r.start..r.end.min(new.len());
+        if r.is_empty() {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(prev) if r.start - prev.end <= IPS_MERGE_GAP => prev.end = r.end,
+            _ => ranges.push(r),
+        }
+    }
+
+    let mut ret = IPS_MAGIC.to_vec();
+    for r in ranges {
+        let mut raw_start = r.start;
+        let mut i = r.start;
+        while i < r.end {
+            let run_len = new[i..r.end]
+                .iter()
+                .take(IPS_MAX_RECORD_LEN)
+                .take_while(|&&b| b == new[i])
+                .count();
+            if run_len >= IPS_MIN_RLE_LEN {
+                push_raw(&mut ret, new, raw_start..i);
+                push_rle(&mut ret, new, i..(i + run_len));
+                raw_start = i + run_len;
+            }
+            i += run_len;
+        }
+        push_raw(&mut ret, new, raw_start..r.end);
+    }
+    ret.extend_from_slice(IPS_EOF);
+    if new.len() < orig.len() {
+        ret.extend_from_slice(&(new.len() as u32).to_be_bytes()[1..]);
+    }
+
+    Ok(ret)
+}
+
+/// Applies an IPS patch, including the optional truncation extension
+pub fn apply_ips(data: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if !patch.starts_with(IPS_MAGIC) {
+        return Err(PatchError::UnknownFormat);
+    }
+
+    let read = |pos: &mut usize, len: usize| -> Result<&[u8], PatchError> {
+        let ret = patch.get(*pos..(*pos + len)).ok_or(PatchError::Truncated)?;
+        *pos += len;
+        Ok(ret)
+    };
+    let read_u16 = |pos: &mut usize| -> Result<usize, PatchError> {
+        let b = read(pos, 2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]) as usize)
+    };
+    let read_u24 = |pos: &mut usize| -> Result<usize, PatchError> {
+        let b = read(pos, 3)?;
+        Ok(u32::from_be_bytes([0, b[0], b[1], b[2]]) as usize)
+    };
+
+    let mut out = data.to_vec();
+    let mut pos = IPS_MAGIC.len();
+    loop {
+        if read(&mut pos, 3)? == IPS_EOF {
+            break;
+        }
+        pos -= 3;
+
+        let offset = read_u24(&mut pos)?;
+        let len = read_u16(&mut pos)?;
+        if len != 0 {
+            let record = read(&mut pos, len)?;
+            if out.len() < offset + len {
+                out.resize(offset + len, 0);
+            }
+            out[offset..(offset + len)].copy_from_slice(record);
+        } else {
+            let len = read_u16(&mut pos)?;
+            let val = read(&mut pos, 1)?[0];
+            if out.len() < offset + len {
+                out.resize(offset + len, 0);
+            }
+            out[offset..(offset + len)].fill(val);
+        }
+    }
+
+    if patch.len() - pos >= 3 {
+        let truncated_len = read_u24(&mut pos)?;
+        out.truncate(truncated_len);
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simple_records() {
+        let orig = [0u8; 32];
+        let mut new = orig;
+        new[1] = 1;
+        new[2] = 2;
+        new[20] = 3;
+        // close enough to be merged with the previous record
+        new[24] = 4;
+
+        let patch = create_ips(&orig, &new).unwrap();
+        assert_eq!(
+            patch,
+            b"PATCH\x00\x00\x01\x00\x02\x01\x02\x00\x00\x14\x00\x05\x03\x00\x00\x00\x04EOF"
+        );
+    }
+
+    #[test]
+    fn rle_records() {
+        let orig = [0u8; 64];
+        let mut new = orig;
+        new[3] = 1;
+        new[4..40].fill(0xaa);
+        new[40] = 2;
+
+        let patch = create_ips(&orig, &new).unwrap();
+        assert_eq!(
+            patch,
+            b"PATCH\x00\x00\x03\x00\x01\x01\x00\x00\x04\x00\x00\x00\x24\xaa\x00\x00\x28\x00\x01\x02EOF"
+        );
+    }
+
+    #[test]
+    fn extend_and_truncate() {
+        let patch = create_ips(&[1, 2], &[1, 2, 3]).unwrap();
+        assert_eq!(patch, b"PATCH\x00\x00\x02\x00\x01\x03EOF");
+
+        let patch = create_ips(&[1, 2, 3, 4], &[1, 5]).unwrap();
+        assert_eq!(patch, b"PATCH\x00\x00\x01\x00\x01\x05EOF\x00\x00\x02");
+    }
+
+    #[test]
+    fn avoid_eof_offset() {
+        let orig = vec![0u8; IPS_EOF_OFFSET + 16];
+        let mut new = orig.clone();
+        new[IPS_EOF_OFFSET] = 1;
+
+        let patch = create_ips(&orig, &new).unwrap();
+        assert_eq!(patch, b"PATCH\x45\x4f\x45\x00\x02\x00\x01EOF");
+    }
+
+    #[test]
+    fn long_records_split() {
+        let orig = vec![0u8; 0x20000];
+        let new = (0..0x20000)
+            .map(|i| (i % 251 + 1) as u8)
+            .collect::<Vec<_>>();
+
+        let patch = create_ips(&orig, &new).unwrap();
+        assert_eq!(&patch[5..10], b"\x00\x00\x00\xff\xff");
+        assert_eq!(
+            &patch[(10 + 0xffff)..(15 + 0xffff)],
+            b"\x00\xff\xff\xff\xff"
+        );
+        assert_eq!(patch.len(), 5 + 3 * 5 + 0x20000 + 3);
+    }
+
+    #[test]
+    fn apply() {
+        let orig = [0u8; 8];
+        let patch = b"PATCH\x00\x00\x01\x00\x02\x01\x02\x00\x00\x05\x00\x00\x00\x05\x07EOF";
+        assert_eq!(
+            apply_ips(&orig, patch),
+            Ok(vec![0, 1, 2, 0, 0, 7, 7, 7, 7, 7])
+        );
+    }
+
+    #[test]
+    fn apply_truncate() {
+        let patch = b"PATCH\x00\x00\x01\x00\x01\x05EOF\x00\x00\x02";
+        assert_eq!(apply_ips(&[1, 2, 3, 4], patch), Ok(vec![1, 5]));
+    }
+
+    #[test]
+    fn apply_truncated_patch() {
+        assert_eq!(
+            apply_ips(&[0; 4], b"PATCH\x00\x00\x01\x00\x04\x01"),
+            Err(PatchError::Truncated)
+        );
+        assert_eq!(apply_ips(&[0; 4], b"PATCH"), Err(PatchError::Truncated));
+    }
+
+    #[test]
+    fn roundtrip() {
+        let orig = (0..0x500000)
+            .map(|i| ((i * 13) >> 4) as u8)
+            .collect::<Vec<_>>();
+        let mut new = orig.clone();
+        new[0x10..0x30].fill(0x55);
+        new[0x1234] ^= 0xff;
+        new[IPS_EOF_OFFSET] ^= 0xff;
+        new[(IPS_EOF_OFFSET + 1)..(IPS_EOF_OFFSET + 20)].fill(0);
+
+        let patch = create_ips(&orig, &new).unwrap();
+        assert_eq!(apply_ips(&orig, &patch), Ok(new.clone()));
+
+        new.truncate(0x1000);
+        let patch = create_ips(&orig, &new).unwrap();
+        assert_eq!(apply_ips(&orig, &patch), Ok(new.clone()));
+
+        new.resize(0x2000, 0);
+        let patch = create_ips(&orig[..0x1000], &new).unwrap();
+        assert_eq!(apply_ips(&orig[..0x1000], &patch), Ok(new));
+    }
+
+    #[test]
+    fn too_large() {
+        let new = vec![0u8; IPS_MAX_LEN + 1];
+        assert_eq!(
+            create_ips(&[], &new),
+            Err(PatchError::TooLarge {
+                len: IPS_MAX_LEN + 1,
+                max_len: IPS_MAX_LEN
+            })
+        );
+
+        // the largest file can't be the result of truncating
+        let orig = vec![0u8; IPS_MAX_LEN + 1];
+        let new = &orig[..IPS_MAX_LEN];
+        assert_eq!(
+            create_ips(&orig, new),
+            Err(PatchError::TooLarge {
+                len: IPS_MAX_LEN,
+                max_len: IPS_MAX_LEN - 1
+            })
+        );
+        let patch = create_ips(&orig, &new[..(IPS_MAX_LEN - 1)]).unwrap();
+        assert_eq!(patch, b"PATCHEOF\xff\xff\xff");
+        assert!(create_ips(&orig[..IPS_MAX_LEN], new).is_ok());
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt;
+
+pub mod bps;
+pub mod ips;
+pub mod ups;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumKind {
+    Source,
+    Target,
+    Patch,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PatchError {
+    TooLarge {
+        len: usize,
+        max_len: usize,
+    },
+    UnknownFormat,
+    Truncated,
+    Corrupt,
+    ChecksumMismatch {
+        kind: ChecksumKind,
+        expected: u32,
+        got: u32,
+    },
+}
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::TooLarge { len, max_len } => write!(
+                f,
+                "file is {} bytes, but this patch format only supports up to {} bytes",
+                len, max_len
+            ),
+            PatchError::UnknownFormat => write!(f, "not a recognized patch format"),
+            PatchError::Truncated => write!(f, "patch is truncated"),
+            PatchError::Corrupt => write!(f, "patch is corrupt"),
+            PatchError::ChecksumMismatch {
+                kind: ChecksumKind::Source,
+                expected,
+                got,
+            } => write!(
+                f,
+                "wrong base file: patch expects CRC32 {:08X}, but the loaded file has {:08X}",
+                expected, got
+            ),
+            PatchError::ChecksumMismatch {
+                kind: ChecksumKind::Target,
+                expected,
+                got,
+            } => write!(
+                f,
+                "patched file has CRC32 {:08X}, but the patch expects {:08X}",
+                got, expected
+            ),
+            PatchError::ChecksumMismatch {
+                kind: ChecksumKind::Patch,
+                expected,
+                got,
+            } => write!(
+                f,
+                "patch is corrupt: CRC32 is {:08X}, but should be {:08X}",
+                got, expected
+            ),
+        }
+    }
+}
+impl std::error::Error for PatchError {}
+
+/// Applies a patch in any supported format, detected from its header
+pub fn apply_patch(data: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(bps::BPS_MAGIC) {
+        bps::apply_bps(data, patch)
+    } else if patch.starts_with(ups::UPS_MAGIC) {
+        ups::apply_ups(data, patch)
+    } else if patch.starts_with(ips::IPS_MAGIC) {
+        ips::apply_ips(data, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+// Variable-length integers as used by BPS and UPS
+fn write_vlq(out: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let x = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(0x80 | x);
+            break;
+        }
+        out.push(x);
+        n -= 1;
+    }
+}
+
+fn read_vlq(patch: &[u8], pos: &mut usize) -> Result<u64, PatchError> {
+    let mut data = 0u64;
+    let mut shift = 1u64;
+    loop {
+        let x = *patch.get(*pos).ok_or(PatchError::Truncated)?;
+        *pos += 1;
+        data = (x as u64 & 0x7f)
+            .checked_mul(shift)
+            .and_then(|x| data.checked_add(x))
+            .ok_or(PatchError::Corrupt)?;
+        if x & 0x80 != 0 {
+            return Ok(data);
+        }
+        shift = shift.checked_shl(7).ok_or(PatchError::Corrupt)?;
+        data = data.checked_add(shift).ok_or(PatchError::Corrupt)?;
+    }
+}
+
+fn read_vlq_usize(patch: &[u8], pos: &mut usize) -> Result<usize, PatchError> {
+    usize::try_from(read_vlq(patch, pos)?).map_err(|_| PatchError::Corrupt)
+}
+
+// Both BPS and UPS end with source, target and patch CRC32s
+fn push_footer(out: &mut Vec<u8>, source: &[u8], target: &[u8]) {
+    out.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+    out.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+    let patch_crc = crc32fast::hash(out);
+    out.extend_from_slice(&patch_crc.to_le_bytes());
+}
+
+struct Footer {
+    source_crc: u32,
+    target_crc: u32,
+}
+
+fn check_footer(patch: &[u8], header_len: usize) -> Result<Footer, PatchError> {
+    if patch.len() < header_len + 12 {
+        return Err(PatchError::Truncated);
+    }
+    let footer = &patch[(patch.len() - 12)..];
+    let read_u32 = |i: usize| u32::from_le_bytes(footer[i..(i + 4)].try_into().unwrap());
+
+    let patch_crc = crc32fast::hash(&patch[..(patch.len() - 4)]);
+    if patch_crc != read_u32(8) {
+        return Err(PatchError::ChecksumMismatch {
+            kind: ChecksumKind::Patch,
+            expected: read_u32(8),
+            got: patch_crc,
+        });
+    }
+
+    Ok(Footer {
+        source_crc: read_u32(0),
+        target_crc: read_u32(4),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vlq_roundtrip() {
+        for n in [0, 1, 0x7f, 0x80, 0x81, 0x3fff, 0x4000, 0x4080, 0xdeadbeef] {
+            let mut buf = Vec::new();
+            write_vlq(&mut buf, n);
+            let mut pos = 0;
+            assert_eq!(read_vlq(&buf, &mut pos), Ok(n));
+            assert_eq!(pos, buf.len());
+        }
+
+        let mut buf = Vec::new();
+        write_vlq(&mut buf, 0x80);
+        assert_eq!(buf, [0x00, 0x80]);
+    }
+
+    #[test]
+    fn vlq_truncated() {
+        let mut pos = 0;
+        assert_eq!(read_vlq(&[0x00], &mut pos), Err(PatchError::Truncated));
+    }
+
+    #[test]
+    fn unknown_format() {
+        assert_eq!(apply_patch(&[], b"garbage"), Err(PatchError::UnknownFormat));
+    }
+}
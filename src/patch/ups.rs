@@ -0,0 +1,179 @@
+use super::{check_footer, push_footer, read_vlq_usize, write_vlq, ChecksumKind, PatchError};
+
+pub const UPS_MAGIC: &[u8] = b"UPS1";
+
+/// Creates a UPS patch which turns `source` into `target`
+pub fn create_ups(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut ret = UPS_MAGIC.to_vec();
+    write_vlq(&mut ret, source.len() as u64);
+    write_vlq(&mut ret, target.len() as u64);
+
+    // both files are treated as being zero-padded to the same length
+    let len = source.len().max(target.len());
+    let source_at = |i: usize| source.get(i).copied().unwrap_or(0);
+    let target_at = |i: usize| target.get(i).copied().unwrap_or(0);
+
+    let mut last_end = 0;
+    let mut i = 0;
+    while i < len {
+        if source_at(i) == target_at(i) {
+            i += 1;
+            continue;
+        }
+
+        write_vlq(&mut ret, (i - last_end) as u64);
+        while i < len && source_at(i) != target_at(i) {
+            ret.push(source_at(i) ^ target_at(i));
+            i += 1;
+        }
+        ret.push(0);
+        i += 1;
+        last_end = i;
+    }
+
+    push_footer(&mut ret, source, target);
+    ret
+}
+
+/// Applies a UPS patch.
+/// UPS patches are reversible, so this also accepts `data` being the patch's target file.
+pub fn apply_ups(data: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if !patch.starts_with(UPS_MAGIC) {
+        return Err(PatchError::UnknownFormat);
+    }
+    let footer = check_footer(patch, UPS_MAGIC.len())?;
+    let body_end = patch.len() - 12;
+
+    let mut pos = UPS_MAGIC.len();
+    let source_len = read_vlq_usize(patch, &mut pos)?;
+    let target_len = read_vlq_usize(patch, &mut pos)?;
+
+    let data_crc = crc32fast::hash(data);
+    let (out_len, expected_crc) = if data_crc == footer.source_crc && data.len() == source_len {
+        (target_len, footer.target_crc)
+    } else if data_crc == footer.target_crc && data.len() == target_len {
+        (source_len, footer.source_crc)
+    } else {
+        return Err(PatchError::ChecksumMismatch {
+            kind: ChecksumKind::Source,
+            expected: footer.source_crc,
+            got: data_crc,
+        });
+    };
+
+    // the lengths are untrusted. Every changed byte past the end of `data` takes a byte of
+    // patch, so anything longer could only be zero padding; refuse it rather than allocate
+    // whatever the patch asks for.
+    let max_len = data.len().saturating_add(body_end - pos);
+    if source_len.max(target_len) > max_len {
+        return Err(PatchError::Corrupt);
+    }
+    let mut out = data.to_vec();
+    out.resize(source_len.max(target_len), 0);
+
+    let mut i: usize = 0;
+    while pos < body_end {
+        let skip = read_vlq_usize(patch, &mut pos)?;
+        i = i.checked_add(skip).ok_or(PatchError::Corrupt)?;
+        loop {
+            if pos >= body_end {
+                return Err(PatchError::Truncated);
+            }
+            let x = patch[pos];
+            pos += 1;
+            if x == 0 {
+                i = i.checked_add(1).ok_or(PatchError::Corrupt)?;
+                break;
+            }
+            *out.get_mut(i).ok_or(PatchError::Corrupt)? ^= x;
+            i += 1;
+        }
+    }
+    out.truncate(out_len);
+
+    let out_crc = crc32fast::hash(&out);
+    if out_crc != expected_crc {
+        return Err(PatchError::ChecksumMismatch {
+            kind: ChecksumKind::Target,
+            expected: expected_crc,
+            got: out_crc,
+        });
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let source = b"Hello, world! This is the original file.".to_vec();
+        let mut target = source.clone();
+        target[0] = b'J';
+        target[7..12].copy_from_slice(b"there");
+        target.extend_from_slice(b" Plus more.");
+
+        let patch = create_ups(&source, &target);
+        assert_eq!(apply_ups(&source, &patch), Ok(target.clone()));
+        // and backwards
+        assert_eq!(apply_ups(&target, &patch), Ok(source.clone()));
+    }
+
+    #[test]
+    fn shrink() {
+        let source = [1, 2, 3, 4, 5, 6];
+        let target = [1, 7, 3];
+
+        let patch = create_ups(&source, &target);
+        assert_eq!(apply_ups(&source, &patch), Ok(target.to_vec()));
+        assert_eq!(apply_ups(&target, &patch), Ok(source.to_vec()));
+    }
+
+    #[test]
+    fn known_patch() {
+        let patch = create_ups(&[0, 0, 0, 0], &[0, 1, 2, 0]);
+        assert_eq!(&patch[..10], b"UPS1\x84\x84\x81\x01\x02\x00");
+        assert_eq!(patch.len(), 10 + 12);
+    }
+
+    #[test]
+    fn wrong_source() {
+        let patch = create_ups(&[1, 2, 3], &[1, 2, 4]);
+        assert!(matches!(
+            apply_ups(&[9, 9, 9], &patch),
+            Err(PatchError::ChecksumMismatch {
+                kind: ChecksumKind::Source,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn malicious_lengths() {
+        let source = [1, 2, 3, 4];
+
+        // a target far bigger than anything that could be allocated, with valid checksums
+        let mut patch = UPS_MAGIC.to_vec();
+        write_vlq(&mut patch, source.len() as u64);
+        write_vlq(&mut patch, 1 << 50);
+        write_vlq(&mut patch, 0);
+        patch.extend_from_slice(&[1, 0]);
+        push_footer(&mut patch, &source, &[]);
+        assert_eq!(apply_ups(&source, &patch), Err(PatchError::Corrupt));
+    }
+
+    #[test]
+    fn corrupt_patch() {
+        let mut patch = create_ups(&[1, 2, 3], &[1, 2, 4]);
+        patch[6] ^= 1;
+        assert!(matches!(
+            apply_ups(&[1, 2, 3], &patch),
+            Err(PatchError::ChecksumMismatch {
+                kind: ChecksumKind::Patch,
+                ..
+            })
+        ));
+    }
+}
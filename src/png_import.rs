@@ -0,0 +1,313 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum PngImportError {
+    Decode(png::DecodingError),
+    UnsupportedFormat(png::ColorType, png::BitDepth),
+    WrongDimensions {
+        expected: (usize, usize),
+        got: (usize, usize),
+    },
+    IndexOutOfRange {
+        x: usize,
+        y: usize,
+        index: u8,
+        num_colors: usize,
+    },
+    NoPalette,
+}
+impl fmt::Display for PngImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PngImportError::Decode(e) => write!(f, "could not decode PNG: {}", e),
+            PngImportError::UnsupportedFormat(color, depth) => {
+                write!(f, "unsupported PNG format {:?} ({:?})", color, depth)
+            }
+            PngImportError::WrongDimensions { expected, got } => write!(
+                f,
+                "image is {} x {} px, but the current view is {} x {} px",
+                got.0, got.1, expected.0, expected.1
+            ),
+            PngImportError::IndexOutOfRange {
+                x,
+                y,
+                index,
+                num_colors,
+            } => write!(
+                f,
+                "pixel ({}, {}) uses color {}, but the current codec only has {} colors",
+                x, y, index, num_colors
+            ),
+            PngImportError::NoPalette => write!(f, "the current codec is not paletted"),
+        }
+    }
+}
+impl std::error::Error for PngImportError {}
+impl From<png::DecodingError> for PngImportError {
+    fn from(e: png::DecodingError) -> Self {
+        PngImportError::Decode(e)
+    }
+}
+
+pub struct IndexedImage {
+    pub w: usize,
+    pub h: usize,
+    pub px: Vec<u8>,
+    /// Number of pixels that had no exact match in the palette
+    pub lossy_px: usize,
+}
+impl IndexedImage {
+    pub fn check_fits(
+        &self,
+        w: usize,
+        h: usize,
+        num_palette_colors: usize,
+    ) -> Result<(), PngImportError> {
+        if (self.w, self.h) != (w, h) {
+            return Err(PngImportError::WrongDimensions {
+                expected: (w, h),
+                got: (self.w, self.h),
+            });
+        }
+        if num_palette_colors == 0 {
+            return Err(PngImportError::NoPalette);
+        }
+        for y in 0..h {
+            for x in 0..w {
+                let index = self.px[y * w + x];
+                if index as usize >= num_palette_colors {
+                    return Err(PngImportError::IndexOutOfRange {
+                        x,
+                        y,
+                        index,
+                        num_colors: num_palette_colors,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn decode_indexed_png(bytes: &[u8]) -> Result<IndexedImage, PngImportError> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::IDENTITY);
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+
+    let bit_depth = match (info.color_type, info.bit_depth) {
+        (png::ColorType::Indexed, png::BitDepth::One) => 1,
+        (png::ColorType::Indexed, png::BitDepth::Two) => 2,
+        (png::ColorType::Indexed, png::BitDepth::Four) => 4,
+        (png::ColorType::Indexed, png::BitDepth::Eight) => 8,
+        (color, depth) => return Err(PngImportError::UnsupportedFormat(color, depth)),
+    };
+
+    let w = info.width as usize;
+    let h = info.height as usize;
+    let mut px = Vec::with_capacity(w * h);
+    for y in 0..h {
+        let line = &buf[(y * info.line_size)..((y + 1) * info.line_size)];
+        for x in 0..w {
+            // sub-byte pixels are packed starting from the MSB
+            let bit_pos = x * bit_depth;
+            let shift = 8 - bit_depth - bit_pos % 8;
+            let mask = ((1u16 << bit_depth) - 1) as u8;
+            px.push((line[bit_pos / 8] >> shift) & mask);
+        }
+    }
+
+    Ok(IndexedImage {
+        w,
+        h,
+        px,
+        lossy_px: 0,
+    })
+}
+
+fn nearest_color(pal: &[(u8, u8, u8)], c: (u8, u8, u8)) -> (u8, bool) {
+    let dist = |p: &(u8, u8, u8)| {
+        let dr = p.0 as i32 - c.0 as i32;
+        let dg = p.1 as i32 - c.1 as i32;
+        let db = p.2 as i32 - c.2 as i32;
+        dr * dr + dg * dg + db * db
+    };
+
+    let (i, p) = pal.iter().enumerate().min_by_key(|(_, p)| dist(p)).unwrap();
+    (i as u8, dist(p) != 0)
+}
+
+/// Decodes a non-indexed PNG, mapping every pixel to the nearest color in `pal`.
+/// Alpha is ignored.
+pub fn decode_rgb_png(bytes: &[u8], pal: &[(u8, u8, u8)]) -> Result<IndexedImage, PngImportError> {
+    if pal.is_empty() {
+        return Err(PngImportError::NoPalette);
+    }
+
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+
+    let get_rgb: fn(&[u8]) -> (u8, u8, u8) = match info.color_type {
+        png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha => |p| (p[0], p[0], p[0]),
+        png::ColorType::Rgb | png::ColorType::Rgba => |p| (p[0], p[1], p[2]),
+        color => return Err(PngImportError::UnsupportedFormat(color, info.bit_depth)),
+    };
+    let bytes_per_px = info.color_type.samples();
+
+    let w = info.width as usize;
+    let h = info.height as usize;
+    let mut px = Vec::with_capacity(w * h);
+    let mut lossy_px = 0;
+    for y in 0..h {
+        let line = &buf[(y * info.line_size)..((y + 1) * info.line_size)];
+        for x in 0..w {
+            let c = get_rgb(&line[(x * bytes_per_px)..((x + 1) * bytes_per_px)]);
+            let (i, lossy) = nearest_color(pal, c);
+            px.push(i);
+            if lossy {
+                lossy_px += 1;
+            }
+        }
+    }
+
+    Ok(IndexedImage { w, h, px, lossy_px })
+}
+
+/// Decodes any PNG into palette indices.
+/// Indexed images are used as-is, everything else is quantized to `pal`.
+pub fn decode_png(bytes: &[u8], pal: &[(u8, u8, u8)]) -> Result<IndexedImage, PngImportError> {
+    let reader = png::Decoder::new(bytes).read_info()?;
+    if reader.info().color_type == png::ColorType::Indexed {
+        decode_indexed_png(bytes)
+    } else {
+        decode_rgb_png(bytes, pal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(w: u32, h: u32, depth: png::BitDepth, data: &[u8]) -> Vec<u8> {
+        let mut ret = Vec::new();
+        let mut png_encoder = png::Encoder::new(&mut ret, w, h);
+        png_encoder.set_color(png::ColorType::Indexed);
+        png_encoder.set_depth(depth);
+        png_encoder.set_palette(vec![0u8; 3 * 16]);
+        let mut png_writer = png_encoder.write_header().unwrap();
+        png_writer.write_image_data(data).unwrap();
+        png_writer.finish().unwrap();
+        ret
+    }
+
+    #[test]
+    fn decode_8bpp() {
+        let png = encode(3, 2, png::BitDepth::Eight, &[0, 1, 2, 3, 4, 5]);
+        let img = decode_indexed_png(&png).unwrap();
+        assert_eq!((img.w, img.h), (3, 2));
+        assert_eq!(img.px, [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn decode_2bpp() {
+        let png = encode(
+            5,
+            2,
+            png::BitDepth::Two,
+            &[0b00011011, 0b01000000, 0xff, 0x00],
+        );
+        let img = decode_indexed_png(&png).unwrap();
+        assert_eq!((img.w, img.h), (5, 2));
+        assert_eq!(img.px, [0, 1, 2, 3, 1, 3, 3, 3, 3, 0]);
+    }
+
+    fn encode_truecolor(w: u32, h: u32, color: png::ColorType, data: &[u8]) -> Vec<u8> {
+        let mut ret = Vec::new();
+        let mut png_encoder = png::Encoder::new(&mut ret, w, h);
+        png_encoder.set_color(color);
+        png_encoder.set_depth(png::BitDepth::Eight);
+        let mut png_writer = png_encoder.write_header().unwrap();
+        png_writer.write_image_data(data).unwrap();
+        png_writer.finish().unwrap();
+        ret
+    }
+
+    #[test]
+    fn reject_rgb() {
+        let png = encode_truecolor(1, 1, png::ColorType::Rgb, &[1, 2, 3]);
+        assert!(matches!(
+            decode_indexed_png(&png),
+            Err(PngImportError::UnsupportedFormat(png::ColorType::Rgb, _))
+        ));
+    }
+
+    const TEST_PAL: [(u8, u8, u8); 4] = [(0, 0, 0), (255, 0, 0), (0, 255, 0), (255, 255, 255)];
+
+    #[test]
+    fn quantize_rgb() {
+        let png = encode_truecolor(
+            4,
+            1,
+            png::ColorType::Rgb,
+            &[0, 0, 0, 250, 10, 0, 0, 255, 0, 200, 200, 200],
+        );
+        let img = decode_png(&png, &TEST_PAL).unwrap();
+        assert_eq!((img.w, img.h), (4, 1));
+        assert_eq!(img.px, [0, 1, 2, 3]);
+        assert_eq!(img.lossy_px, 2);
+    }
+
+    #[test]
+    fn quantize_rgba_and_gray() {
+        let png = encode_truecolor(
+            2,
+            1,
+            png::ColorType::Rgba,
+            &[255, 0, 0, 0, 255, 255, 255, 255],
+        );
+        let img = decode_png(&png, &TEST_PAL).unwrap();
+        assert_eq!(img.px, [1, 3]);
+        assert_eq!(img.lossy_px, 0);
+
+        let png = encode_truecolor(2, 1, png::ColorType::Grayscale, &[10, 255]);
+        let img = decode_png(&png, &TEST_PAL[..2]).unwrap();
+        assert_eq!(img.px, [0, 1]);
+        assert_eq!(img.lossy_px, 2);
+    }
+
+    #[test]
+    fn indexed_not_quantized() {
+        let png = encode(2, 1, png::BitDepth::Eight, &[7, 9]);
+        let img = decode_png(&png, &TEST_PAL).unwrap();
+        assert_eq!(img.px, [7, 9]);
+        assert_eq!(img.lossy_px, 0);
+    }
+
+    #[test]
+    fn check_fits() {
+        let img = IndexedImage {
+            w: 2,
+            h: 1,
+            px: vec![1, 4],
+            lossy_px: 0,
+        };
+        assert!(matches!(
+            img.check_fits(2, 2, 4),
+            Err(PngImportError::WrongDimensions { .. })
+        ));
+        assert!(matches!(
+            img.check_fits(2, 1, 4),
+            Err(PngImportError::IndexOutOfRange {
+                x: 1,
+                y: 0,
+                index: 4,
+                ..
+            })
+        ));
+        assert!(img.check_fits(2, 1, 16).is_ok());
+    }
+}
@@ -4,6 +4,7 @@ use bitvec::{
     prelude::*,
 };
 use std::marker::PhantomData;
+use std::ops::Range;
 
 use crate::AbstractPixelTarget;
 
@@ -20,6 +21,24 @@ pub trait TileCodec {
         tiles_w: usize,
         tiles_h: usize,
     );
+    /// Returns `false`, leaving `bytes` untouched, if the pixel lies past the end of `bytes`
+    fn write_px(
+        &self,
+        bytes: &mut [u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        x: usize,
+        y: usize,
+        i: u8,
+    ) -> bool;
+
+    /// Bits that make up tile `tile_i`, relative to the first tile.
+    /// Most codecs store each tile as one run of `bits_per_tile` bits.
+    #[allow(clippy::single_range_in_vec_init)]
+    fn tile_bit_ranges(&self, tile_i: usize) -> Vec<Range<usize>> {
+        let bits_per_tile = self.bits_per_tile();
+        vec![(tile_i * bits_per_tile)..((tile_i + 1) * bits_per_tile)]
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -85,6 +104,7 @@ impl<
         1 << PLANES
     }
 
+    #[allow(clippy::let_and_return)]
     fn bits_per_tile(&self) -> usize {
         let data_bits_per_row = TILE_W + TILE_W_PAD;
         let data_bits_per_plane = data_bits_per_row * TILE_H + PLANE_PAD;
@@ -141,6 +161,45 @@ impl<
             }
         }
     }
+
+    fn write_px(
+        &self,
+        bytes: &mut [u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        x: usize,
+        y: usize,
+        i: u8,
+    ) -> bool {
+        debug_assert!(PLANES <= 8);
+        let bits = bytes.view_bits_mut::<DataBitOrder>();
+
+        let data_bits_per_row = TILE_W + TILE_W_PAD;
+        let data_bits_per_plane = data_bits_per_row * TILE_H + PLANE_PAD;
+        let data_bits_per_tile = data_bits_per_plane * PLANES + FINAL_PAD;
+
+        let tile_i = (y / TILE_H) * tiles_w + x / TILE_W;
+        let px_x = x % TILE_W;
+        let px_y = y % TILE_H;
+
+        let px = [i];
+        let px_bv = px.view_bits::<PlaneBitOrder>();
+        let bit_idx = |plane: usize| {
+            bit_offs as usize
+                + tile_i * data_bits_per_tile
+                + plane * data_bits_per_plane
+                + px_y * data_bits_per_row
+                + px_x
+        };
+        // a pixel that only partly fits is left alone entirely
+        if (0..PLANES).any(|plane| bit_idx(plane) >= bits.len()) {
+            return false;
+        }
+        for plane in 0..PLANES {
+            bits.set(bit_idx(plane), px_bv[plane]);
+        }
+        true
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -201,6 +260,7 @@ impl<
         1 << PLANES
     }
 
+    #[allow(clippy::let_and_return)]
     fn bits_per_tile(&self) -> usize {
         let data_bits_per_plane = TILE_W + TILE_W_PAD;
         let data_bits_per_row = data_bits_per_plane * PLANES;
@@ -257,6 +317,44 @@ impl<
             }
         }
     }
+
+    fn write_px(
+        &self,
+        bytes: &mut [u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        x: usize,
+        y: usize,
+        i: u8,
+    ) -> bool {
+        debug_assert!(PLANES <= 8);
+        let bits = bytes.view_bits_mut::<DataBitOrder>();
+
+        let data_bits_per_plane = TILE_W + TILE_W_PAD;
+        let data_bits_per_row = data_bits_per_plane * PLANES;
+        let data_bits_per_tile = data_bits_per_row * TILE_H + FINAL_PAD;
+
+        let tile_i = (y / TILE_H) * tiles_w + x / TILE_W;
+        let px_x = x % TILE_W;
+        let px_y = y % TILE_H;
+
+        let px = [i];
+        let px_bv = px.view_bits::<PlaneBitOrder>();
+        let bit_idx = |plane: usize| {
+            bit_offs as usize
+                + tile_i * data_bits_per_tile
+                + plane * data_bits_per_plane
+                + px_y * data_bits_per_row
+                + px_x
+        };
+        if (0..PLANES).any(|plane| bit_idx(plane) >= bits.len()) {
+            return false;
+        }
+        for plane in 0..PLANES {
+            bits.set(bit_idx(plane), px_bv[plane]);
+        }
+        true
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -297,6 +395,7 @@ impl<
         1 << BPP
     }
 
+    #[allow(clippy::let_and_return)]
     fn bits_per_tile(&self) -> usize {
         let data_bits_per_row = BPP * TILE_W + TILE_W_PAD;
         let data_bits_per_tile = data_bits_per_row * TILE_H + FINAL_PAD;
@@ -351,6 +450,575 @@ impl<
             }
         }
     }
+
+    fn write_px(
+        &self,
+        bytes: &mut [u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        x: usize,
+        y: usize,
+        i: u8,
+    ) -> bool {
+        debug_assert!(BPP <= 8);
+        let bits = bytes.view_bits_mut::<DataBitOrder>();
+
+        let data_bits_per_row = BPP * TILE_W + TILE_W_PAD;
+        let data_bits_per_tile = data_bits_per_row * TILE_H + FINAL_PAD;
+
+        let tile_i = (y / TILE_H) * tiles_w + x / TILE_W;
+        let px_x = x % TILE_W;
+        let px_y = y % TILE_H;
+
+        let px = [i];
+        let px_bv = px.view_bits::<Lsb0>();
+        let bit_idx = |b_i: usize| {
+            bit_offs as usize
+                + tile_i * data_bits_per_tile
+                + px_y * data_bits_per_row
+                + px_x * BPP
+                + b_i
+        };
+        if (0..BPP).any(|b_i| bit_idx(b_i) >= bits.len()) {
+            return false;
+        }
+        for b_i in 0..BPP {
+            bits.set(bit_idx(b_i), px_bv[b_i]);
+        }
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PlanarGroupInterleavedTileGraphics<
+    DataBitOrder: BitOrder,
+    PlaneBitOrder: BitOrder,
+    const PLANES: usize,
+    const PLANES_PER_GROUP: usize,
+    const TILE_W: usize,
+    const TILE_H: usize,
+    const TILE_W_PAD: usize,
+    const GROUP_PAD: usize,
+    const FINAL_PAD: usize,
+> {
+    _pd: PhantomData<(DataBitOrder, PlaneBitOrder)>,
+}
+impl<
+        DataBitOrder: BitOrder,
+        PlaneBitOrder: BitOrder,
+        const PLANES: usize,
+        const PLANES_PER_GROUP: usize,
+        const TILE_W: usize,
+        const TILE_H: usize,
+        const TILE_W_PAD: usize,
+        const GROUP_PAD: usize,
+        const FINAL_PAD: usize,
+    >
+    PlanarGroupInterleavedTileGraphics<
+        DataBitOrder,
+        PlaneBitOrder,
+        PLANES,
+        PLANES_PER_GROUP,
+        TILE_W,
+        TILE_H,
+        TILE_W_PAD,
+        GROUP_PAD,
+        FINAL_PAD,
+    >
+{
+    pub const fn new() -> Self {
+        Self { _pd: PhantomData }
+    }
+
+    // The last group can have fewer planes than the others (e.g. SNES 3bpp)
+    const fn planes_in_group(group: usize) -> usize {
+        let remaining = PLANES - group * PLANES_PER_GROUP;
+        if remaining < PLANES_PER_GROUP {
+            remaining
+        } else {
+            PLANES_PER_GROUP
+        }
+    }
+
+    const fn data_bits_per_group(group: usize) -> usize {
+        let data_bits_per_plane = TILE_W + TILE_W_PAD;
+        let data_bits_per_group_row = data_bits_per_plane * Self::planes_in_group(group);
+        data_bits_per_group_row * TILE_H + GROUP_PAD
+    }
+
+    fn plane_bit_idx(plane: usize, px_x: usize, px_y: usize) -> usize {
+        let group = plane / PLANES_PER_GROUP;
+        let data_bits_per_plane = TILE_W + TILE_W_PAD;
+        let data_bits_per_group_row = data_bits_per_plane * Self::planes_in_group(group);
+
+        // all groups before this one are full
+        group * Self::data_bits_per_group(0)
+            + px_y * data_bits_per_group_row
+            + (plane % PLANES_PER_GROUP) * data_bits_per_plane
+            + px_x
+    }
+}
+impl<
+        DataBitOrder: BitOrder,
+        PlaneBitOrder: BitOrder,
+        const PLANES: usize,
+        const PLANES_PER_GROUP: usize,
+        const TILE_W: usize,
+        const TILE_H: usize,
+        const TILE_W_PAD: usize,
+        const GROUP_PAD: usize,
+        const FINAL_PAD: usize,
+    > TileCodec
+    for PlanarGroupInterleavedTileGraphics<
+        DataBitOrder,
+        PlaneBitOrder,
+        PLANES,
+        PLANES_PER_GROUP,
+        TILE_W,
+        TILE_H,
+        TILE_W_PAD,
+        GROUP_PAD,
+        FINAL_PAD,
+    >
+{
+    fn num_palette_colors(&self) -> usize {
+        1 << PLANES
+    }
+
+    fn bits_per_tile(&self) -> usize {
+        let groups = PLANES.div_ceil(PLANES_PER_GROUP);
+        (0..groups).map(Self::data_bits_per_group).sum::<usize>() + FINAL_PAD
+    }
+
+    fn tile_width(&self) -> usize {
+        TILE_W
+    }
+
+    fn tile_height(&self) -> usize {
+        TILE_H
+    }
+
+    fn render(
+        &self,
+        r: &mut dyn AbstractPixelTarget,
+        bytes: &[u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        tiles_h: usize,
+    ) {
+        debug_assert!(PLANES <= 8);
+        let bits = bytes.view_bits::<DataBitOrder>();
+
+        let data_bits_per_tile = self.bits_per_tile();
+
+        for tile_y in 0..tiles_h {
+            for tile_x in 0..tiles_w {
+                let tile_i = tile_y * tiles_w + tile_x;
+                for px_y in 0..TILE_H {
+                    for px_x in 0..TILE_W {
+                        let mut px = [0u8; 1];
+                        let px_bv = px.view_bits_mut::<PlaneBitOrder>();
+                        for plane in 0..PLANES {
+                            let bit_idx = bit_offs as usize
+                                + tile_i * data_bits_per_tile
+                                + Self::plane_bit_idx(plane, px_x, px_y);
+                            if bit_idx >= bits.len() {
+                                return;
+                            }
+                            px_bv.set(plane, bits[bit_idx]);
+                        }
+
+                        r.draw_px_pal(tile_x * TILE_W + px_x, tile_y * TILE_H + px_y, px[0]);
+                    }
+                }
+            }
+        }
+    }
+
+    fn write_px(
+        &self,
+        bytes: &mut [u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        x: usize,
+        y: usize,
+        i: u8,
+    ) -> bool {
+        debug_assert!(PLANES <= 8);
+        let bits = bytes.view_bits_mut::<DataBitOrder>();
+
+        let data_bits_per_tile = self.bits_per_tile();
+
+        let tile_i = (y / TILE_H) * tiles_w + x / TILE_W;
+        let px_x = x % TILE_W;
+        let px_y = y % TILE_H;
+
+        let px = [i];
+        let px_bv = px.view_bits::<PlaneBitOrder>();
+        let bit_idx = |plane: usize| {
+            bit_offs as usize + tile_i * data_bits_per_tile + Self::plane_bit_idx(plane, px_x, px_y)
+        };
+        if (0..PLANES).any(|plane| bit_idx(plane) >= bits.len()) {
+            return false;
+        }
+        for plane in 0..PLANES {
+            bits.set(bit_idx(plane), px_bv[plane]);
+        }
+        true
+    }
+}
+
+// SNES Mode 7 VRAM is made of 16-bit words, where the low byte is part of
+// a 128x128 tilemap and the high byte is part of 256 8x8 8bpp tiles.
+// Only whole bytes are ever read, so the bit order only matters when the view
+// starts mid-byte. Bits are counted LSB first to match the little-endian words,
+// so such an offset shifts the data like shifting the words themselves would.
+// MSB first would instead shift bits across the bytes in big-endian order.
+const MODE7_MAP_W: usize = 128;
+const MODE7_WORDS: usize = MODE7_MAP_W * MODE7_MAP_W;
+
+fn mode7_bytes(bytes: &[u8], bit_offs: usize, high: bool, words: usize) -> Vec<u8> {
+    let bits = bytes.view_bits::<Lsb0>();
+    (0..words)
+        .map(|i| bit_offs + i * 16 + if high { 8 } else { 0 })
+        .take_while(|&bit_idx| bit_idx + 8 <= bits.len())
+        .map(|bit_idx| bits[bit_idx..(bit_idx + 8)].load_le::<u8>())
+        .collect()
+}
+
+fn mode7_store_px(bytes: &mut [u8], bit_offs: usize, word: usize, i: u8) -> bool {
+    let bits = bytes.view_bits_mut::<Lsb0>();
+    let bit_idx = bit_offs + word * 16 + 8;
+    if bit_idx + 8 > bits.len() {
+        return false;
+    }
+    bits[bit_idx..(bit_idx + 8)].store_le(i);
+    true
+}
+
+/// Only the tile pixel data of Mode 7 VRAM, as regular 8bpp tiles
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SNESMode7TileGraphics;
+impl SNESMode7TileGraphics {
+    pub const fn new() -> Self {
+        Self
+    }
+}
+impl TileCodec for SNESMode7TileGraphics {
+    fn num_palette_colors(&self) -> usize {
+        256
+    }
+
+    fn bits_per_tile(&self) -> usize {
+        64 * 16
+    }
+
+    fn tile_width(&self) -> usize {
+        8
+    }
+
+    fn tile_height(&self) -> usize {
+        8
+    }
+
+    fn render(
+        &self,
+        r: &mut dyn AbstractPixelTarget,
+        bytes: &[u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        tiles_h: usize,
+    ) {
+        let px = mode7_bytes(bytes, bit_offs as usize, true, tiles_w * tiles_h * 64);
+        TileGraphics8bpp::new().render(r, &px, 0, tiles_w, tiles_h);
+    }
+
+    fn write_px(
+        &self,
+        bytes: &mut [u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        x: usize,
+        y: usize,
+        i: u8,
+    ) -> bool {
+        let tile_i = (y / 8) * tiles_w + x / 8;
+        mode7_store_px(
+            bytes,
+            bit_offs as usize,
+            tile_i * 64 + (y % 8) * 8 + x % 8,
+            i,
+        )
+    }
+}
+
+/// Mode 7 VRAM composited the way the PPU would display it.
+/// Each "tile" of this codec is an entire 1024x1024 pixel map.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SNESMode7MapGraphics;
+impl SNESMode7MapGraphics {
+    pub const fn new() -> Self {
+        Self
+    }
+}
+impl TileCodec for SNESMode7MapGraphics {
+    fn num_palette_colors(&self) -> usize {
+        256
+    }
+
+    fn bits_per_tile(&self) -> usize {
+        MODE7_WORDS * 16
+    }
+
+    fn tile_width(&self) -> usize {
+        MODE7_MAP_W * 8
+    }
+
+    fn tile_height(&self) -> usize {
+        MODE7_MAP_W * 8
+    }
+
+    fn render(
+        &self,
+        r: &mut dyn AbstractPixelTarget,
+        bytes: &[u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        tiles_h: usize,
+    ) {
+        for tile_y in 0..tiles_h {
+            for tile_x in 0..tiles_w {
+                let tile_i = tile_y * tiles_w + tile_x;
+                let map_bit_offs = bit_offs as usize + tile_i * self.bits_per_tile();
+                let map = mode7_bytes(bytes, map_bit_offs, false, MODE7_WORDS);
+                let px = mode7_bytes(bytes, map_bit_offs, true, MODE7_WORDS);
+
+                for map_y in 0..MODE7_MAP_W {
+                    for map_x in 0..MODE7_MAP_W {
+                        let Some(&tile) = map.get(map_y * MODE7_MAP_W + map_x) else {
+                            return;
+                        };
+                        for px_y in 0..8 {
+                            for px_x in 0..8 {
+                                let Some(&i) = px.get(tile as usize * 64 + px_y * 8 + px_x) else {
+                                    continue;
+                                };
+                                r.draw_px_pal(
+                                    tile_x * self.tile_width() + map_x * 8 + px_x,
+                                    tile_y * self.tile_height() + map_y * 8 + px_y,
+                                    i,
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn write_px(
+        &self,
+        bytes: &mut [u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        x: usize,
+        y: usize,
+        i: u8,
+    ) -> bool {
+        let tile_i = (y / self.tile_height()) * tiles_w + x / self.tile_width();
+        let map_bit_offs = bit_offs as usize + tile_i * self.bits_per_tile();
+        let x = x % self.tile_width();
+        let y = y % self.tile_height();
+
+        // edits go to whichever tile the map points at
+        let map_word = (y / 8) * MODE7_MAP_W + x / 8;
+        let Some(&tile) = mode7_bytes(bytes, map_bit_offs + map_word * 16, false, 1).first() else {
+            return false;
+        };
+        mode7_store_px(
+            bytes,
+            map_bit_offs,
+            tile as usize * 64 + (y % 8) * 8 + x % 8,
+            i,
+        )
+    }
+}
+
+fn swap16(bytes: &mut [u8]) {
+    for word in bytes.chunks_exact_mut(2) {
+        word.swap(0, 1);
+    }
+}
+
+/// Wraps another codec, swapping the bytes of every 16-bit word first.
+/// This is needed for little-endian hardware which reads pixels MSB-first out of whole words.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ByteSwapped16TileGraphics<C: TileCodec> {
+    inner: C,
+}
+impl<C: TileCodec> ByteSwapped16TileGraphics<C> {
+    pub const fn wrap(inner: C) -> Self {
+        Self { inner }
+    }
+}
+impl<C: TileCodec> TileCodec for ByteSwapped16TileGraphics<C> {
+    fn num_palette_colors(&self) -> usize {
+        self.inner.num_palette_colors()
+    }
+
+    fn bits_per_tile(&self) -> usize {
+        self.inner.bits_per_tile()
+    }
+
+    fn tile_width(&self) -> usize {
+        self.inner.tile_width()
+    }
+
+    fn tile_height(&self) -> usize {
+        self.inner.tile_height()
+    }
+
+    fn render(
+        &self,
+        r: &mut dyn AbstractPixelTarget,
+        bytes: &[u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        tiles_h: usize,
+    ) {
+        let data_bits = bit_offs as usize + tiles_w * tiles_h * self.bits_per_tile();
+        let len = (data_bits.div_ceil(16) * 2).min(bytes.len());
+        let mut swapped = bytes[..len].to_vec();
+        swap16(&mut swapped);
+        self.inner.render(r, &swapped, bit_offs, tiles_w, tiles_h);
+    }
+
+    fn write_px(
+        &self,
+        bytes: &mut [u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        x: usize,
+        y: usize,
+        i: u8,
+    ) -> bool {
+        let tile_i = (y / self.tile_height()) * tiles_w + x / self.tile_width();
+        let tile_start = bit_offs as usize + tile_i * self.bits_per_tile();
+        let start = (tile_start / 16 * 2).min(bytes.len());
+        let end = ((tile_start + self.bits_per_tile()).div_ceil(16) * 2).min(bytes.len());
+
+        swap16(&mut bytes[start..end]);
+        let wrote = self.inner.write_px(bytes, bit_offs, tiles_w, x, y, i);
+        swap16(&mut bytes[start..end]);
+        wrote
+    }
+
+    fn tile_bit_ranges(&self, tile_i: usize) -> Vec<Range<usize>> {
+        // write_px works on whole words, and a tile that starts mid-word because of
+        // the bit offset spills into the word after it
+        self.inner
+            .tile_bit_ranges(tile_i)
+            .into_iter()
+            .map(|r| (r.start / 16 * 16)..(r.end.div_ceil(16) * 16 + 16))
+            .collect()
+    }
+}
+
+/// Neo Geo sprites, as seen with the C ROM pair byte-interleaved (odd chip first).
+/// Each 16x16 tile is stored as its right 8x16 half followed by its left half.
+/// Every row is 4 bytes holding planes 0, 2, 1, 3, with the leftmost pixel in the LSB.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NeoGeoSpriteGraphics;
+impl NeoGeoSpriteGraphics {
+    pub const fn new() -> Self {
+        Self
+    }
+
+    fn plane_bit_idx(plane: usize, px_x: usize, px_y: usize) -> usize {
+        const PLANE_BYTE: [usize; 4] = [0, 2, 1, 3];
+        let half = if px_x < 8 { 1 } else { 0 };
+        (half * 64 + px_y * 4 + PLANE_BYTE[plane]) * 8 + px_x % 8
+    }
+}
+impl TileCodec for NeoGeoSpriteGraphics {
+    fn num_palette_colors(&self) -> usize {
+        16
+    }
+
+    fn bits_per_tile(&self) -> usize {
+        128 * 8
+    }
+
+    fn tile_width(&self) -> usize {
+        16
+    }
+
+    fn tile_height(&self) -> usize {
+        16
+    }
+
+    fn render(
+        &self,
+        r: &mut dyn AbstractPixelTarget,
+        bytes: &[u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        tiles_h: usize,
+    ) {
+        let bits = bytes.view_bits::<Lsb0>();
+
+        for tile_y in 0..tiles_h {
+            for tile_x in 0..tiles_w {
+                let tile_i = tile_y * tiles_w + tile_x;
+                for px_y in 0..16 {
+                    for px_x in 0..16 {
+                        let mut px = [0u8; 1];
+                        let px_bv = px.view_bits_mut::<Lsb0>();
+                        for plane in 0..4 {
+                            let bit_idx = bit_offs as usize
+                                + tile_i * self.bits_per_tile()
+                                + Self::plane_bit_idx(plane, px_x, px_y);
+                            if bit_idx >= bits.len() {
+                                return;
+                            }
+                            px_bv.set(plane, bits[bit_idx]);
+                        }
+
+                        r.draw_px_pal(tile_x * 16 + px_x, tile_y * 16 + px_y, px[0]);
+                    }
+                }
+            }
+        }
+    }
+
+    fn write_px(
+        &self,
+        bytes: &mut [u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        x: usize,
+        y: usize,
+        i: u8,
+    ) -> bool {
+        let bits = bytes.view_bits_mut::<Lsb0>();
+
+        let tile_i = (y / 16) * tiles_w + x / 16;
+
+        let px = [i];
+        let px_bv = px.view_bits::<Lsb0>();
+        let bit_idx = |plane: usize| {
+            bit_offs as usize
+                + tile_i * self.bits_per_tile()
+                + Self::plane_bit_idx(plane, x % 16, y % 16)
+        };
+        if (0..4).any(|plane| bit_idx(plane) >= bits.len()) {
+            return false;
+        }
+        for plane in 0..4 {
+            bits.set(bit_idx(plane), px_bv[plane]);
+        }
+        true
+    }
 }
 
 pub type NESGraphics = PlanarNonInterleavedTileGraphics<Msb0, Lsb0, 2, 8, 8, 0, 0, 0>;
@@ -358,6 +1026,22 @@ pub type GBGraphics = PlanarInterleavedTileGraphics<Msb0, Lsb0, 2, 8, 8, 0, 0>;
 pub type GBATileGraphics4bpp = NonPlanarTileGraphics<Lsb0, 4, 8, 8, 0, 0>;
 pub type GenesisGraphics4bpp = NonPlanarTileGraphics<HiLo, 4, 8, 8, 0, 0>;
 pub type TileGraphics8bpp = NonPlanarTileGraphics<Lsb0, 8, 8, 8, 0, 0>;
+pub type SMSGraphics4bpp = PlanarInterleavedTileGraphics<Msb0, Lsb0, 4, 8, 8, 0, 0>;
+
+pub type SNESGraphics2bpp = GBGraphics;
+pub type SNESGraphics3bpp = PlanarGroupInterleavedTileGraphics<Msb0, Lsb0, 3, 2, 8, 8, 0, 0, 0>;
+pub type SNESGraphics4bpp = PlanarGroupInterleavedTileGraphics<Msb0, Lsb0, 4, 2, 8, 8, 0, 0, 0>;
+pub type SNESGraphics8bpp = PlanarGroupInterleavedTileGraphics<Msb0, Lsb0, 8, 2, 8, 8, 0, 0, 0>;
+
+pub type PCEGraphics4bpp = SNESGraphics4bpp;
+pub type PCESpriteGraphics =
+    ByteSwapped16TileGraphics<PlanarNonInterleavedTileGraphics<Msb0, Lsb0, 4, 16, 16, 0, 0, 0>>;
+
+pub type VirtualBoyGraphics = NonPlanarTileGraphics<Lsb0, 2, 8, 8, 0, 0>;
+pub type NGPGraphics = ByteSwapped16TileGraphics<NonPlanarTileGraphics<HiLo2bpp, 2, 8, 8, 0, 0>>;
+pub type WonderSwanGraphics2bpp = GBGraphics;
+pub type WonderSwanGraphics4bpp = SMSGraphics4bpp;
+pub type WonderSwanPackedGraphics4bpp = GenesisGraphics4bpp;
 
 pub struct HiLo;
 unsafe impl BitOrder for HiLo {
@@ -369,10 +1053,41 @@ unsafe impl BitOrder for HiLo {
     }
 }
 
+/// Like [`HiLo`], but for 2bpp: the leftmost pixel is in the top two bits
+pub struct HiLo2bpp;
+unsafe impl BitOrder for HiLo2bpp {
+    fn at<R>(index: BitIdx<R>) -> BitPos<R>
+    where
+        R: BitRegister,
+    {
+        unsafe { BitPos::new_unchecked(index.into_inner() ^ 6) }
+    }
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
+    /// Renders `tiles_w` x `tiles_h` tiles into palette indices, row by row
+    pub(crate) fn render_tiles(
+        codec: &dyn TileCodec,
+        data: &[u8],
+        bit_offs: u8,
+        tiles_w: usize,
+        tiles_h: usize,
+    ) -> Vec<u8> {
+        let w = tiles_w * codec.tile_width();
+        let mut px = vec![0u8; w * tiles_h * codec.tile_height()];
+        codec.render(
+            &mut crate::InMemoryPixelWriter { w, px: &mut px },
+            data,
+            bit_offs,
+            tiles_w,
+            tiles_h,
+        );
+        px
+    }
+
     #[test]
     fn ensure_obj_safe() {
         let _: &dyn TileCodec;
@@ -381,5 +1096,332 @@ mod tests {
     #[test]
     fn prove_hilo() {
         bitvec::order::verify::<HiLo>(true);
+        bitvec::order::verify::<HiLo2bpp>(true);
+    }
+
+    fn roundtrip(codec: &dyn TileCodec) {
+        let tiles_w = 2;
+        let tiles_h = 2;
+        let w = tiles_w * codec.tile_width();
+        let h = tiles_h * codec.tile_height();
+        let mut data = vec![0u8; codec.bits_per_tile() * tiles_w * tiles_h / 8 + 1];
+
+        let mut expected = vec![0u8; w * h];
+        for y in 0..h {
+            for x in 0..w {
+                let i = ((x * 7 + y * 3) % codec.num_palette_colors()) as u8;
+                codec.write_px(&mut data, 3, tiles_w, x, y, i);
+                expected[y * w + x] = i;
+            }
+        }
+
+        assert_eq!(render_tiles(codec, &data, 3, tiles_w, tiles_h), expected);
+    }
+
+    #[test]
+    fn write_roundtrip() {
+        roundtrip(&NESGraphics::new());
+        roundtrip(&GBGraphics::new());
+        roundtrip(&GBATileGraphics4bpp::new());
+        roundtrip(&GenesisGraphics4bpp::new());
+        roundtrip(&TileGraphics8bpp::new());
+        roundtrip(&SMSGraphics4bpp::new());
+        roundtrip(&SNESGraphics3bpp::new());
+        roundtrip(&SNESGraphics4bpp::new());
+        roundtrip(&SNESGraphics8bpp::new());
+        roundtrip(&SNESMode7TileGraphics::new());
+        roundtrip(&NeoGeoSpriteGraphics::new());
+        roundtrip(&VirtualBoyGraphics::new());
+        roundtrip(&NGPGraphics::wrap(NonPlanarTileGraphics::new()));
+        roundtrip(&PCESpriteGraphics::wrap(
+            PlanarNonInterleavedTileGraphics::new(),
+        ));
+    }
+
+    #[test]
+    fn byte_swapped_undo() {
+        let codec = PCESpriteGraphics::wrap(PlanarNonInterleavedTileGraphics::new());
+        let orig: Vec<u8> = (0..160u32).map(|i| (i * 0x4b) as u8).collect();
+        let mut data = orig.clone();
+        let mut journal = crate::history::EditJournal::default();
+
+        let bit_offs = 3;
+        for bits in codec.tile_bit_ranges(0) {
+            journal.record_bits(&data, (bits.start + bit_offs)..(bits.end + bit_offs));
+        }
+        for y in 0..16 {
+            for x in 0..16 {
+                codec.write_px(&mut data, bit_offs as u8, 1, x, y, 5);
+            }
+        }
+        assert_ne!(data[129], orig[129]);
+        assert!(journal.commit(&data));
+        journal.undo(&mut data);
+        assert_eq!(data, orig);
+    }
+
+    #[test]
+    fn pce_sprite() {
+        let codec = PCESpriteGraphics::wrap(PlanarNonInterleavedTileGraphics::new());
+        assert_eq!(codec.tile_width(), 16);
+        assert_eq!(codec.tile_height(), 16);
+        assert_eq!(codec.bits_per_tile(), 128 * 8);
+
+        // four planes of sixteen little-endian words, bit 15 is the leftmost pixel
+        let mut tile = [0u8; 128];
+        tile[0] = 0x01;
+        tile[1] = 0x80;
+        tile[3 * 32 + 15 * 2 + 1] = 0x01;
+        let px = render_tiles(&codec, &tile, 0, 1, 1);
+        let mut expected = [0u8; 256];
+        expected[0] = 1;
+        expected[15] = 1;
+        expected[15 * 16 + 7] = 8;
+        assert_eq!(px, expected);
+
+        let mut data = [0u8; 128];
+        for (i, &c) in expected.iter().enumerate() {
+            codec.write_px(&mut data, 0, 1, i % 16, i / 16, c);
+        }
+        assert_eq!(data, tile);
+    }
+
+    #[test]
+    fn handheld_2bpp() {
+        // both use one little-endian word per row, but start from opposite ends
+        let mut tile = [0u8; 16];
+        tile[0] = 0b00_00_10_01;
+        tile[1] = 0b11_00_00_00;
+
+        let px = render_tiles(&VirtualBoyGraphics::new(), &tile, 0, 1, 1);
+        assert_eq!(px[..8], [1, 2, 0, 0, 0, 0, 0, 3]);
+
+        let px = render_tiles(
+            &NGPGraphics::wrap(NonPlanarTileGraphics::new()),
+            &tile,
+            0,
+            1,
+            1,
+        );
+        assert_eq!(px[..8], [3, 0, 0, 0, 0, 0, 2, 1]);
+    }
+
+    #[test]
+    fn wonderswan_packed() {
+        let mut tile = [0u8; 32];
+        tile[0] = 0x12;
+        tile[31] = 0xf0;
+        let px = render_tiles(&WonderSwanPackedGraphics4bpp::new(), &tile, 0, 1, 1);
+        assert_eq!(px[..2], [1, 2]);
+        assert_eq!(px[62..], [15, 0]);
+    }
+
+    #[test]
+    fn snes_mode7() {
+        let mut vram = vec![0u8; 0x8000];
+        // map entry (1, 0) uses tile 2, whose first pixel is 0x55
+        vram[2] = 2;
+        vram[2 * 128 + 1] = 0x55;
+        // pixel (7, 7) of tile 0
+        vram[63 * 2 + 1] = 0xaa;
+
+        let px = render_tiles(&SNESMode7TileGraphics::new(), &vram, 0, 2, 1);
+        assert_eq!(px[7 * 16 + 7], 0xaa);
+        assert_eq!(px[0], 0);
+
+        let px = render_tiles(&SNESMode7MapGraphics::new(), &vram, 0, 1, 1);
+        assert_eq!(px[8], 0x55);
+        assert_eq!(px[7 * 1024 + 7], 0xaa);
+        // every other map entry uses tile 0
+        assert_eq!(px[(8 * 100 + 7) * 1024 + 8 * 100 + 7], 0xaa);
+
+        // painting through the map modifies the tile it points to
+        SNESMode7MapGraphics::new().write_px(&mut vram, 0, 1, 9, 0, 0x11);
+        assert_eq!(vram[(2 * 64 + 1) * 2 + 1], 0x11);
+        assert_eq!(vram[2], 2);
+    }
+
+    #[test]
+    fn sms_golden() {
+        // one byte per plane per row, MSB is the leftmost pixel
+        #[rustfmt::skip]
+        let tile = [
+            0x55, 0x33, 0x0f, 0x00,
+            0x55, 0x33, 0x0f, 0xff,
+            0x20, 0x20, 0x20, 0x20,
+            0x10, 0x00, 0x00, 0x00,
+            0x00, 0x08, 0x00, 0x00,
+            0x00, 0x00, 0x04, 0x00,
+            0x00, 0x00, 0x00, 0x02,
+            0x81, 0x80, 0x01, 0x00,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            0, 1, 2, 3, 4, 5, 6, 7,
+            8, 9, 10, 11, 12, 13, 14, 15,
+            0, 0, 15, 0, 0, 0, 0, 0,
+            0, 0, 0, 1, 0, 0, 0, 0,
+            0, 0, 0, 0, 2, 0, 0, 0,
+            0, 0, 0, 0, 0, 4, 0, 0,
+            0, 0, 0, 0, 0, 0, 8, 0,
+            3, 0, 0, 0, 0, 0, 0, 5,
+        ];
+
+        let codec = SMSGraphics4bpp::new();
+        assert_eq!(codec.bits_per_tile(), 32 * 8);
+        assert_eq!(render_tiles(&codec, &tile, 0, 1, 1), expected);
+
+        let mut data = [0u8; 32];
+        for (i, &c) in expected.iter().enumerate() {
+            codec.write_px(&mut data, 0, 1, i % 8, i / 8, c);
+        }
+        assert_eq!(data, tile);
+    }
+
+    #[test]
+    fn sms_font_glyph() {
+        // "F" from the IBM PC 8x8 BIOS font as SMS text routines upload it,
+        // in color 1: each glyph row followed by three empty planes
+        #[rustfmt::skip]
+        let tile = [
+            0xfe, 0x00, 0x00, 0x00,
+            0x62, 0x00, 0x00, 0x00,
+            0x68, 0x00, 0x00, 0x00,
+            0x78, 0x00, 0x00, 0x00,
+            0x68, 0x00, 0x00, 0x00,
+            0x60, 0x00, 0x00, 0x00,
+            0xf0, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        #[rustfmt::skip]
+        let expected = concat!(
+            "#######.",
+            ".##...#.",
+            ".##.#...",
+            ".####...",
+            ".##.#...",
+            ".##.....",
+            "####....",
+            "........",
+        );
+        let expected: Vec<u8> = expected.bytes().map(|c| (c == b'#') as u8).collect();
+        assert_eq!(
+            render_tiles(&SMSGraphics4bpp::new(), &tile, 0, 1, 1),
+            expected
+        );
+    }
+
+    #[test]
+    fn neogeo_sprite() {
+        // planes 0/1 come from the odd chip (C1), planes 2/3 from the even chip (C2)
+        let mut c1 = [0u8; 64];
+        let mut c2 = [0u8; 64];
+        // leftmost pixel of the top row lives in the second half, LSB first
+        c1[32] = 0x01;
+        c2[33] = 0x01;
+        // rightmost pixel of the bottom row
+        c1[15 * 2 + 1] = 0x80;
+        c2[15 * 2] = 0x80;
+        let tile = crate::interleave::interleave_bytes(&[&c1, &c2]);
+
+        let codec = NeoGeoSpriteGraphics::new();
+        let px = render_tiles(&codec, &tile, 0, 1, 1);
+        let mut expected = [0u8; 256];
+        expected[0] = 0b1001;
+        expected[255] = 0b0110;
+        assert_eq!(px, expected);
+
+        let mut data = [0u8; 128];
+        for (i, &c) in expected.iter().enumerate() {
+            codec.write_px(&mut data, 0, 1, i % 16, i / 16, c);
+        }
+        assert_eq!(data.as_slice(), tile);
+    }
+
+    #[test]
+    fn snes_layout() {
+        assert_eq!(SNESGraphics4bpp::new().bits_per_tile(), 32 * 8);
+        assert_eq!(SNESGraphics8bpp::new().bits_per_tile(), 64 * 8);
+
+        // plane pairs are row-interleaved, then the pairs follow each other
+        let mut data = [0u8; 32];
+        SNESGraphics4bpp::new().write_px(&mut data, 0, 1, 1, 3, 0b1011);
+        let mut expected = [0u8; 32];
+        expected[6] = 0x40;
+        expected[7] = 0x40;
+        expected[23] = 0x40;
+        assert_eq!(data, expected);
+
+        // the third plane of 3bpp is one byte per row after the first pair
+        assert_eq!(SNESGraphics3bpp::new().bits_per_tile(), 24 * 8);
+        let mut data = [0u8; 48];
+        SNESGraphics3bpp::new().write_px(&mut data, 0, 2, 8, 2, 0b110);
+        SNESGraphics3bpp::new().write_px(&mut data, 0, 2, 15, 7, 0b100);
+        let mut expected = [0u8; 48];
+        expected[24 + 5] = 0x80;
+        expected[24 + 16 + 2] = 0x80;
+        expected[24 + 16 + 7] = 0x01;
+        assert_eq!(data, expected);
+
+        let mut data = [0u8; 64];
+        SNESGraphics8bpp::new().write_px(&mut data, 0, 1, 7, 7, 0b1100_0001);
+        let mut expected = [0u8; 64];
+        expected[14] = 0x01;
+        expected[63] = 0x01;
+        expected[62] = 0x01;
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn write_nes() {
+        let mut data = [0u8; 16];
+        NESGraphics::new().write_px(&mut data, 0, 1, 1, 2, 3);
+        let mut expected = [0u8; 16];
+        expected[2] = 0x40;
+        expected[10] = 0x40;
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn write_genesis() {
+        let mut data = [0u8; 32];
+        GenesisGraphics4bpp::new().write_px(&mut data, 0, 1, 0, 0, 0xa);
+        GenesisGraphics4bpp::new().write_px(&mut data, 0, 1, 1, 0, 0x5);
+        assert_eq!(data[0], 0xa5);
+    }
+
+    /// Writes pixels of a tile that is cut off halfway, which splits planar pixels.
+    /// Pixels that don't fit must not change the data at all.
+    pub(crate) fn check_write_past_end(codec: &dyn TileCodec) {
+        let mut data = vec![0xff; codec.bits_per_tile() / 16];
+        // the corners are enough for huge tiles
+        let coords = |n: usize| (0..n).filter(move |&i| n <= 64 || i < 8 || i >= n - 8);
+        let mut failed = 0;
+        for y in coords(codec.tile_height()) {
+            for x in coords(codec.tile_width()) {
+                let before = data.clone();
+                if !codec.write_px(&mut data, 0, 1, x, y, 0) {
+                    assert_eq!(data, before, "({}, {})", x, y);
+                    failed += 1;
+                }
+            }
+        }
+        assert!(failed > 0);
+    }
+
+    #[test]
+    fn write_past_end() {
+        // the second plane of rows 4-7 is missing
+        let mut data = [0u8; 12];
+        assert!(NESGraphics::new().write_px(&mut data, 0, 1, 0, 0, 3));
+        assert!(!NESGraphics::new().write_px(&mut data, 0, 1, 0, 4, 3));
+        assert_eq!(data[4], 0);
+        assert!(!SNESMode7TileGraphics::new().write_px(&mut data, 0, 1, 0, 1, 3));
+
+        for codec in crate::BUILTIN_GRAPHICS_CODECS {
+            if let Some(tile_codec) = codec.tile_codec {
+                check_write_past_end(tile_codec);
+            }
+        }
     }
 }